@@ -1,19 +1,272 @@
-use clap::{App, Arg};
+use crate::client::{ClientId, HOUSE_ACCOUNT};
+use crate::engine::NegativeBalancePolicy;
+use crate::interest::DayCount;
+use crate::monitoring::ChargebackPolicy;
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::str::FromStr;
+
+pub enum Command {
+    /// Process a CSV file of transactions and output account statements.
+    Process,
+
+    /// Verify the ledger invariants of a CSV account statement snapshot.
+    Verify,
+
+    /// Report the structural problems of a CSV file of transactions without
+    /// processing it.
+    Validate,
+}
 
 pub struct Args {
-    pub csv_file: String
+    pub command: Command,
+    pub csv_files: Vec<String>,
+    pub negative_balance_policy: NegativeBalancePolicy,
+    pub verify: bool,
+    pub account_config: Option<String>,
+    pub fee_schedule: Option<String>,
+    pub house_account: ClientId,
+    pub fee_report: Option<String>,
+    pub interest_rates: Option<String>,
+    pub day_count: DayCount,
+    pub accrual_report: Option<String>,
+    pub exchange_rates: Option<String>,
+    pub exchange_report: Option<String>,
+    pub journal_report: Option<String>,
+    pub trial_balance: Option<String>,
+    pub withdrawal_limits: Option<String>,
+    pub risk_rules: Option<String>,
+    pub alerts: Option<String>,
+    pub blocklist: Option<String>,
+    pub quarantine: Option<String>,
+    pub chargeback_policy: ChargebackPolicy,
+    pub monitoring_report: Option<String>,
+    pub client_registry: Option<String>,
+    pub strict_registry: bool,
+    pub duplicates: Option<String>,
+    pub atomic: bool,
+    pub batches: Option<String>,
+    pub reorder_window: u64,
+    pub orphans: Option<String>,
+    pub warnings: Option<String>,
+    pub dialect: Option<String>,
+    pub camt053_files: Vec<String>,
+    pub bank_accounts: Option<String>,
+    pub mt940_files: Vec<String>,
+    pub reconciliation: Option<String>,
 }
 
 impl Args {
     pub fn parse() -> Self {
         let matches = App::new("bank")
             .version("0.1.0")
+            .setting(AppSettings::SubcommandsNegateReqs)
             .arg(Arg::with_name("csv_file")
-                .takes_value(true).required(true).help("path of CSV file to read from"))
+                .takes_value(true).required_unless_one(&["camt053", "mt940"]).multiple(true)
+                .help("paths of CSV files to read from, in order"))
+            .arg(Arg::with_name("negative_balance_policy")
+                .long("negative-balance-policy").takes_value(true).global(true)
+                .possible_values(&["reject", "allow-debt", "hold-available"])
+                .default_value("allow-debt")
+                .help("how to handle disputes that would drive available funds below zero"))
+            .arg(Arg::with_name("verify")
+                .long("verify")
+                .help("verify ledger invariants after every transaction"))
+            .arg(Arg::with_name("account_config")
+                .long("account-config").takes_value(true)
+                .help("path of CSV file containing per-account configuration"))
+            .arg(Arg::with_name("fee_schedule")
+                .long("fee-schedule").takes_value(true)
+                .help("path of CSV file containing the fee schedule"))
+            .arg(Arg::with_name("house_account")
+                .long("house-account").takes_value(true).default_value(HOUSE_ACCOUNT)
+                .help("client id of the account that fees are credited to"))
+            .arg(Arg::with_name("fee_report")
+                .long("fee-report").takes_value(true)
+                .help("path of CSV file to write the itemized fee report to"))
+            .arg(Arg::with_name("interest_rates")
+                .long("interest-rates").takes_value(true)
+                .help("path of CSV file containing the interest rate tiers"))
+            .arg(Arg::with_name("day_count")
+                .long("day-count").takes_value(true)
+                .possible_values(&["act/360", "act/365"])
+                .default_value("act/365")
+                .help("day count convention used when accruing interest"))
+            .arg(Arg::with_name("accrual_report")
+                .long("accrual-report").takes_value(true)
+                .help("path of CSV file to write the interest accrual report to"))
+            .arg(Arg::with_name("exchange_rates")
+                .long("exchange-rates").takes_value(true)
+                .help("path of CSV file containing the effective-dated exchange rates"))
+            .arg(Arg::with_name("exchange_report")
+                .long("exchange-report").takes_value(true)
+                .help("path of CSV file to write the exchange report to"))
+            .arg(Arg::with_name("journal_report")
+                .long("journal-report").takes_value(true)
+                .help("path of CSV file to write the double-entry journal to"))
+            .arg(Arg::with_name("trial_balance")
+                .long("trial-balance").takes_value(true)
+                .help("path of CSV file to write the trial balance to"))
+            .arg(Arg::with_name("withdrawal_limits")
+                .long("withdrawal-limits").takes_value(true)
+                .help("path of CSV file containing the withdrawal limits of each account tier"))
+            .arg(Arg::with_name("risk_rules")
+                .long("risk-rules").takes_value(true)
+                .help("path of CSV file containing the risk rules to evaluate"))
+            .arg(Arg::with_name("alerts")
+                .long("alerts").takes_value(true)
+                .help("path of CSV file to write the alerts raised by risk rules to"))
+            .arg(Arg::with_name("blocklist")
+                .long("blocklist").takes_value(true)
+                .help("path of CSV file containing the clients to screen transactions against"))
+            .arg(Arg::with_name("quarantine")
+                .long("quarantine").takes_value(true)
+                .help("path of CSV file to write transactions quarantined by screening to"))
+            .arg(Arg::with_name("chargeback_lock_count")
+                .long("chargeback-lock-count").takes_value(true).default_value("1")
+                .validator(is_lock_count)
+                .help("number of chargebacks that locks an account, none to disable"))
+            .arg(Arg::with_name("chargeback_lock_ratio")
+                .long("chargeback-lock-ratio").takes_value(true).validator(is_ratio)
+                .help("ratio of chargebacks to deposits that locks an account"))
+            .arg(Arg::with_name("chargeback_min_deposits")
+                .long("chargeback-min-deposits").takes_value(true).default_value("0")
+                .validator(is_count::<u32>)
+                .help("number of deposits before the chargeback ratio applies"))
+            .arg(Arg::with_name("monitoring_report")
+                .long("monitoring-report").takes_value(true)
+                .help("path of CSV file to write per-client dispute and chargeback ratios to"))
+            .arg(Arg::with_name("client_registry")
+                .long("client-registry").takes_value(true)
+                .help("path of CSV or JSON file containing the registered clients"))
+            .arg(Arg::with_name("strict_registry")
+                .long("strict-registry").requires("client_registry")
+                .help("only open accounts for clients in the client registry"))
+            .arg(Arg::with_name("duplicates")
+                .long("duplicates").takes_value(true)
+                .help("path of CSV file to write rows skipped as already applied to"))
+            .arg(Arg::with_name("atomic")
+                .long("atomic")
+                .help("apply each input file all-or-nothing, rolling it back if any row fails"))
+            .arg(Arg::with_name("batches")
+                .long("batches").takes_value(true)
+                .help("path of CSV file to write committed and rolled back batches to"))
+            .arg(Arg::with_name("reorder_window")
+                .long("reorder-window").takes_value(true).default_value("0")
+                .validator(is_count::<u64>)
+                .help("number of rows to park disputes of transactions that haven't arrived yet"))
+            .arg(Arg::with_name("orphans")
+                .long("orphans").takes_value(true)
+                .help("path of CSV file to write expired disputes, resolves and chargebacks to"))
+            .arg(Arg::with_name("warnings")
+                .long("warnings").takes_value(true)
+                .help("path of CSV file to write ignored disputes, resolves and chargebacks to"))
+            .arg(Arg::with_name("dialect")
+                .long("dialect").takes_value(true).global(true)
+                .help("path of CSV file describing the delimiter, columns and types of input files"))
+            .arg(Arg::with_name("camt053")
+                .long("camt053").takes_value(true).multiple(true).number_of_values(1)
+                .requires("bank_accounts")
+                .help("path of ISO 20022 camt.053 bank statement to import, after any CSV files"))
+            .arg(Arg::with_name("bank_accounts")
+                .long("bank-accounts").takes_value(true)
+                .help("path of CSV file mapping bank account references to client ids"))
+            .arg(Arg::with_name("mt940")
+                .long("mt940").takes_value(true).multiple(true).number_of_values(1)
+                .requires("bank_accounts")
+                .help("path of SWIFT MT940 bank statement to import, after any camt.053 statements"))
+            .arg(Arg::with_name("reconciliation")
+                .long("reconciliation").takes_value(true)
+                .help("path of CSV file to write MT940 balances that don't match the accounts to"))
+            .subcommand(SubCommand::with_name("verify")
+                .about("verify the ledger invariants of an account statement snapshot")
+                .arg(Arg::with_name("csv_file")
+                    .takes_value(true).required(true).help("path of CSV snapshot to read from")))
+            .subcommand(SubCommand::with_name("validate")
+                .about("report the structural problems of a transaction file without processing it")
+                .arg(Arg::with_name("csv_file")
+                    .takes_value(true).required(true).help("path of CSV file to validate")))
             .get_matches();
 
+        let (command, csv_files) = match matches.subcommand() {
+            ("verify", Some(sub)) => (Command::Verify, sub.values_of("csv_file")),
+            ("validate", Some(sub)) => (Command::Validate, sub.values_of("csv_file")),
+            _ => (Command::Process, matches.values_of("csv_file")),
+        };
+
         Self {
-            csv_file: matches.value_of("csv_file").unwrap_or_default().to_string(),
+            command,
+            csv_files: csv_files.map(|files| files.map(String::from).collect()).unwrap_or_default(),
+            negative_balance_policy: matches.value_of("negative_balance_policy")
+                .unwrap_or_default().parse().unwrap_or_default(),
+            verify: matches.is_present("verify"),
+            account_config: matches.value_of("account_config").map(String::from),
+            fee_schedule: matches.value_of("fee_schedule").map(String::from),
+            house_account: matches.value_of("house_account")
+                .unwrap_or_default().parse().unwrap_or_default(),
+            fee_report: matches.value_of("fee_report").map(String::from),
+            interest_rates: matches.value_of("interest_rates").map(String::from),
+            day_count: matches.value_of("day_count")
+                .unwrap_or_default().parse().unwrap_or_default(),
+            accrual_report: matches.value_of("accrual_report").map(String::from),
+            exchange_rates: matches.value_of("exchange_rates").map(String::from),
+            exchange_report: matches.value_of("exchange_report").map(String::from),
+            journal_report: matches.value_of("journal_report").map(String::from),
+            trial_balance: matches.value_of("trial_balance").map(String::from),
+            withdrawal_limits: matches.value_of("withdrawal_limits").map(String::from),
+            risk_rules: matches.value_of("risk_rules").map(String::from),
+            alerts: matches.value_of("alerts").map(String::from),
+            blocklist: matches.value_of("blocklist").map(String::from),
+            quarantine: matches.value_of("quarantine").map(String::from),
+            chargeback_policy: ChargebackPolicy {
+                lock_count: matches.value_of("chargeback_lock_count")
+                    .and_then(|count| count.parse().ok()),
+                lock_ratio: matches.value_of("chargeback_lock_ratio")
+                    .and_then(|ratio| ratio.parse().ok()),
+                min_deposits: matches.value_of("chargeback_min_deposits")
+                    .unwrap_or_default().parse().unwrap_or_default(),
+            },
+            monitoring_report: matches.value_of("monitoring_report").map(String::from),
+            client_registry: matches.value_of("client_registry").map(String::from),
+            strict_registry: matches.is_present("strict_registry"),
+            duplicates: matches.value_of("duplicates").map(String::from),
+            atomic: matches.is_present("atomic"),
+            batches: matches.value_of("batches").map(String::from),
+            reorder_window: matches.value_of("reorder_window")
+                .unwrap_or_default().parse().unwrap_or_default(),
+            orphans: matches.value_of("orphans").map(String::from),
+            warnings: matches.value_of("warnings").map(String::from),
+            dialect: matches.value_of("dialect").map(String::from),
+            camt053_files: matches.values_of("camt053")
+                .map(|files| files.map(String::from).collect()).unwrap_or_default(),
+            bank_accounts: matches.value_of("bank_accounts").map(String::from),
+            mt940_files: matches.values_of("mt940")
+                .map(|files| files.map(String::from).collect()).unwrap_or_default(),
+            reconciliation: matches.value_of("reconciliation").map(String::from),
         }
     }
 }
+
+/// Validates that an argument is a whole number that fits the parsed type.
+fn is_count<T: FromStr>(value: String) -> Result<(), String> {
+    match value.parse::<T>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("{} is not a whole number", value)),
+    }
+}
+
+/// Validates that an argument is a positive whole number, or none.
+fn is_lock_count(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        _ if value == "none" => Ok(()),
+        Ok(count) if count > 0 => Ok(()),
+        _ => Err(format!("{} is not a positive whole number or none", value)),
+    }
+}
+
+/// Validates that an argument is a positive ratio.
+fn is_ratio(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(ratio) if ratio.is_finite() && ratio > 0.0 => Ok(()),
+        _ => Err(format!("{} is not a positive ratio", value)),
+    }
+}
@@ -1,19 +1,222 @@
-use banking_transactions::args::Args;
+use banking_transactions::args::{Args, Command};
+use banking_transactions::camt;
+use banking_transactions::dialect::Dialect;
 use banking_transactions::engine::Ledger;
+use banking_transactions::errors::StatementError;
+use banking_transactions::fees::FeeSchedule;
+use banking_transactions::fx::RateTable;
+use banking_transactions::interest::InterestSchedule;
+use banking_transactions::limits::WithdrawalLimits;
+use banking_transactions::mt940;
+use banking_transactions::registry::{BankAccounts, ClientRegistry};
+use banking_transactions::risk::RiskRules;
+use banking_transactions::screening::Blocklist;
+use banking_transactions::validate::Validation;
+use std::fs;
 use std::path::PathBuf;
 use std::process;
 
 fn main() {
     let args = Args::parse();
 
-    let ledger = match Ledger::try_from(PathBuf::from(args.csv_file)) {
-        Ok(ledger) => ledger,
-        Err(err) => {
-            eprintln!("failed to process input file: {}", err);
+    if let Command::Verify = args.command {
+        if let Err(err) = Ledger::verify_snapshot(
+            PathBuf::from(&args.csv_files[0]),
+            args.negative_balance_policy,
+        ) {
+            eprintln!("snapshot failed verification: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let dialect = match args.dialect {
+        Some(path) => match Dialect::try_from(PathBuf::from(path)) {
+            Ok(dialect) => dialect,
+            Err(err) => {
+                eprintln!("failed to load dialect: {}", err);
+                process::exit(1);
+            }
+        },
+        None => Dialect::default(),
+    };
+
+    if let Command::Validate = args.command {
+        let validation = match Validation::from_path(PathBuf::from(&args.csv_files[0]), &dialect) {
+            Ok(validation) => validation,
+            Err(err) => {
+                eprintln!("failed to read input file: {}", err);
+                process::exit(1);
+            }
+        };
+        match validation.generate_report() {
+            Ok(report) => print!("{}", report),
+            Err(err) => {
+                eprintln!("failed to generate validation report: {}", err);
+                process::exit(1);
+            }
+        }
+        if !validation.is_valid() {
             process::exit(1);
         }
+        return;
+    }
+
+    let fee_schedule = match args.fee_schedule {
+        Some(path) => match FeeSchedule::try_from(PathBuf::from(path)) {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                eprintln!("failed to load fee schedule: {}", err);
+                process::exit(1);
+            }
+        },
+        None => FeeSchedule::default(),
+    };
+
+    let interest_schedule = match args.interest_rates {
+        Some(path) => match InterestSchedule::from_path(PathBuf::from(path), args.day_count) {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                eprintln!("failed to load interest rates: {}", err);
+                process::exit(1);
+            }
+        },
+        None => InterestSchedule::default(),
+    };
+
+    let exchange_rates = match args.exchange_rates {
+        Some(path) => match RateTable::try_from(PathBuf::from(path)) {
+            Ok(rates) => rates,
+            Err(err) => {
+                eprintln!("failed to load exchange rates: {}", err);
+                process::exit(1);
+            }
+        },
+        None => RateTable::default(),
+    };
+
+    let withdrawal_limits = match args.withdrawal_limits {
+        Some(path) => match WithdrawalLimits::try_from(PathBuf::from(path)) {
+            Ok(limits) => limits,
+            Err(err) => {
+                eprintln!("failed to load withdrawal limits: {}", err);
+                process::exit(1);
+            }
+        },
+        None => WithdrawalLimits::default(),
+    };
+
+    let risk_rules = match args.risk_rules {
+        Some(path) => match RiskRules::try_from(PathBuf::from(path)) {
+            Ok(rules) => rules,
+            Err(err) => {
+                eprintln!("failed to load risk rules: {}", err);
+                process::exit(1);
+            }
+        },
+        None => RiskRules::default(),
+    };
+
+    let blocklist = match args.blocklist {
+        Some(path) => match Blocklist::try_from(PathBuf::from(path)) {
+            Ok(blocklist) => blocklist,
+            Err(err) => {
+                eprintln!("failed to load blocklist: {}", err);
+                process::exit(1);
+            }
+        },
+        None => Blocklist::default(),
+    };
+
+    let registry = match args.client_registry {
+        Some(path) => match ClientRegistry::try_from(PathBuf::from(path)) {
+            Ok(registry) => Some(registry),
+            Err(err) => {
+                eprintln!("failed to load client registry: {}", err);
+                process::exit(1);
+            }
+        },
+        None => None,
     };
 
+    let bank_accounts = match args.bank_accounts {
+        Some(path) => match BankAccounts::try_from(PathBuf::from(path)) {
+            Ok(accounts) => accounts,
+            Err(err) => {
+                eprintln!("failed to load bank accounts: {}", err);
+                process::exit(1);
+            }
+        },
+        None => BankAccounts::default(),
+    };
+
+    let mut ledger = Ledger::default()
+        .with_negative_balance_policy(args.negative_balance_policy)
+        .with_verification(args.verify)
+        .with_fee_schedule(fee_schedule, args.house_account)
+        .with_interest_schedule(interest_schedule)
+        .with_exchange_rates(exchange_rates)
+        .with_withdrawal_limits(withdrawal_limits)
+        .with_risk_rules(risk_rules)
+        .with_blocklist(blocklist)
+        .with_chargeback_policy(args.chargeback_policy)
+        .with_atomic_files(args.atomic)
+        .with_reorder_window(args.reorder_window)
+        .with_dialect(dialect);
+    // Statements only gain the registry columns when a registry is loaded.
+    if let Some(registry) = registry {
+        ledger = ledger.with_client_registry(registry, args.strict_registry);
+    }
+    if let Some(path) = args.account_config {
+        if let Err(err) = ledger.load_account_config(PathBuf::from(path)) {
+            eprintln!("failed to load account configuration: {}", err);
+            process::exit(1);
+        }
+    }
+    for csv_file in args.csv_files {
+        if let Err(err) = ledger.process_file(PathBuf::from(&csv_file)) {
+            eprintln!("failed to process input file {}: {}", csv_file, err);
+            process::exit(1);
+        }
+    }
+    for statement in args.camt053_files {
+        let transactions = match camt::import_file(PathBuf::from(&statement), &bank_accounts) {
+            Ok(transactions) => transactions,
+            Err(err) => {
+                eprintln!("failed to import bank statement {}: {}", statement, err);
+                process::exit(1);
+            }
+        };
+        for transaction in transactions {
+            if let Err(err) = ledger.process_transaction(&transaction) {
+                eprintln!("failed to process bank statement {}: {}", statement, err);
+                process::exit(1);
+            }
+        }
+    }
+    for statement in args.mt940_files {
+        let statements = match mt940::parse_file(PathBuf::from(&statement)) {
+            Ok(statements) => statements,
+            Err(err) => {
+                eprintln!("failed to import bank statement {}: {}", statement, err);
+                process::exit(1);
+            }
+        };
+        for mt940_statement in statements.iter() {
+            if let Err(err) = ledger.process_mt940_statement(mt940_statement, &bank_accounts) {
+                eprintln!("failed to process bank statement {}: {}", statement, err);
+                process::exit(1);
+            }
+        }
+    }
+    ledger.expire_orphans();
+    if args.verify {
+        if let Err(err) = ledger.verify() {
+            eprintln!("ledger failed verification: {}", err);
+            process::exit(1);
+        }
+    }
+
     let output = match ledger.generate_account_statements() {
         Ok(output) => output,
         Err(err) => {
@@ -22,4 +225,87 @@ fn main() {
         }
     };
     println!("{}", output);
+
+    if let Some(path) = args.fee_report {
+        write_report(path, "fee", ledger.generate_fee_report());
+    }
+
+    if let Some(path) = args.accrual_report {
+        write_report(path, "accrual", ledger.generate_accrual_report());
+    }
+
+    if let Some(path) = args.exchange_report {
+        write_report(path, "exchange", ledger.generate_exchange_report());
+    }
+
+    if let Some(path) = args.alerts {
+        write_report(path, "alert", ledger.generate_alert_report());
+    }
+
+    if let Some(path) = args.quarantine {
+        write_report(path, "quarantine", ledger.generate_quarantine_report());
+    }
+
+    if let Some(path) = args.monitoring_report {
+        write_report(path, "monitoring", ledger.generate_monitoring_report());
+    }
+
+    if let Some(path) = args.duplicates {
+        write_report(path, "duplicate", ledger.generate_duplicate_report());
+    }
+
+    if let Some(path) = args.batches {
+        write_report(path, "batch", ledger.generate_batch_report());
+    }
+
+    if let Some(path) = args.orphans {
+        write_report(path, "orphan", ledger.generate_orphan_report());
+    }
+
+    if let Some(path) = args.warnings {
+        write_report(path, "warning", ledger.generate_warning_report());
+    }
+
+    if let Some(path) = args.reconciliation {
+        write_report(
+            path,
+            "reconciliation",
+            ledger.generate_reconciliation_report(),
+        );
+    }
+
+    if let Some(path) = args.journal_report {
+        write_report(path, "journal", ledger.generate_journal_report());
+    }
+
+    if let Some(path) = args.trial_balance {
+        write_report(path, "trial balance", ledger.generate_trial_balance());
+    }
+
+    // Summarize the rows ignored because of errors on our partners' side.
+    let counts = ledger.warning_counts();
+    if counts.values().any(|count| *count > 0) {
+        let summary: Vec<String> = counts
+            .iter()
+            .map(|(warning, count)| format!("{}={}", warning, count))
+            .collect();
+        eprintln!("ignored partner rows: {}", summary.join(" "));
+    }
+}
+
+/// Writes a generated report to the file at the provided path, exiting the
+/// process if the report could not be generated or written.
+fn write_report(path: String, name: &str, report: Result<String, StatementError>) {
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("failed to generate {} report: {}", name, err);
+            process::exit(1);
+        }
+    };
+
+    if let Err(err) = fs::write(path, report) {
+        eprintln!("failed to write {} report: {}", name, err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,339 @@
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::engine::{Transaction, TransactionKey, TransactionType};
+use crate::errors::ImportError;
+use crate::registry::BankAccounts;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Returns the first child element of the node with the provided name,
+/// ignoring its namespace.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Returns the descendant of the node at the provided path of element names.
+fn find<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| child(node, name))
+}
+
+/// Returns the trimmed text of the descendant at the provided path.
+fn text_at<'a>(node: Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    find(node, path)
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+}
+
+/// A booked entry of a camt.053 statement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CamtEntry {
+    /// The id of the statement the entry belongs to.
+    pub statement: String,
+
+    /// The reference of the bank account the statement is for.
+    pub account: String,
+
+    /// The entry's reference, or its position within the statement if it
+    /// has none.
+    pub reference: String,
+
+    /// The position of the entry within the statement, starting at 1.
+    pub position: u32,
+    pub credit: bool,
+    pub amount: f64,
+    pub currency: Option<Currency>,
+    pub booking_date: Option<Date>,
+}
+
+/// Attempts to read the booked entries of every statement of an ISO 20022
+/// camt.053 bank-to-customer statement document. Pending and informational
+/// entries are skipped.
+pub fn parse_entries(xml: &str) -> Result<Vec<CamtEntry>, ImportError> {
+    let document = Document::parse(xml).map_err(|err| ImportError::Malformed(err.to_string()))?;
+    let root = document.root_element();
+    let statements = match root.tag_name().name() {
+        "Document" => child(root, "BkToCstmrStmt"),
+        "BkToCstmrStmt" => Some(root),
+        _ => None,
+    }
+    .ok_or_else(|| ImportError::Malformed(String::from("not a camt.053 statement")))?;
+
+    let mut entries = Vec::new();
+    for statement in children(statements, "Stmt") {
+        let id = text_at(statement, &["Id"])
+            .ok_or_else(|| ImportError::Malformed(String::from("statement is missing its Id")))?;
+        let account = text_at(statement, &["Acct", "Id", "IBAN"])
+            .or_else(|| text_at(statement, &["Acct", "Id", "Othr", "Id"]))
+            .ok_or_else(|| ImportError::MissingField(id.to_string(), "account"))?;
+
+        for (index, entry) in children(statement, "Ntry").enumerate() {
+            let position = index as u32 + 1;
+            let reference = text_at(entry, &["NtryRef"])
+                .or_else(|| text_at(entry, &["AcctSvcrRef"]))
+                .map(String::from)
+                .unwrap_or_else(|| format!("{}#{}", id, position));
+
+            // The status is a plain code before camt.053.001.08, and a
+            // choice of code or proprietary status since.
+            let status = text_at(entry, &["Sts", "Cd"])
+                .or_else(|| text_at(entry, &["Sts"]))
+                .ok_or_else(|| ImportError::MissingField(reference.clone(), "status"))?;
+            if status != "BOOK" {
+                continue;
+            }
+
+            let amount = find(entry, &["Amt"])
+                .ok_or_else(|| ImportError::MissingField(reference.clone(), "amount"))?;
+            let value = amount.text().unwrap_or_default().trim();
+            let parsed = match value.parse::<f64>() {
+                Ok(parsed) if parsed.is_finite() && parsed >= 0.0 => parsed,
+                _ => return Err(ImportError::InvalidAmount(reference, value.to_string())),
+            };
+            let currency = match amount.attribute("Ccy") {
+                Some(code) => Some(
+                    code.parse::<Currency>()
+                        .map_err(|_| ImportError::InvalidAmount(reference.clone(), code.into()))?,
+                ),
+                None => None,
+            };
+
+            let credit = match text_at(entry, &["CdtDbtInd"]) {
+                Some("CRDT") => true,
+                Some("DBIT") => false,
+                Some(indicator) => {
+                    return Err(ImportError::InvalidIndicator(reference, indicator.into()))
+                }
+                None => {
+                    return Err(ImportError::MissingField(
+                        reference,
+                        "credit/debit indicator",
+                    ))
+                }
+            };
+
+            // Booking dates are either a date or a date and time.
+            let booking_date = text_at(entry, &["BookgDt", "Dt"])
+                .or_else(|| text_at(entry, &["BookgDt", "DtTm"]))
+                .and_then(|date| date.get(..10))
+                .and_then(|date| date.parse().ok());
+
+            entries.push(CamtEntry {
+                statement: id.to_string(),
+                account: account.to_string(),
+                reference,
+                position,
+                credit,
+                amount: parsed,
+                currency,
+                booking_date,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Attempts to import the booked entries of a camt.053 document as
+/// transactions, crediting and debiting the clients their bank accounts are
+/// mapped to. Credits are deposits and debits are withdrawals.
+///
+/// Transactions are namespaced by the id of their statement, and their tx id
+/// is derived from the reference of their entry, which they carry as their
+/// memo, so that importing the same statement again is a no-op. No
+/// transactions are returned if any entry can't be mapped to a client, or if
+/// the references of two entries hash to the same tx id.
+pub fn import(xml: &str, accounts: &BankAccounts) -> Result<Vec<Transaction>, ImportError> {
+    let transactions: Vec<Transaction> = parse_entries(xml)?
+        .into_iter()
+        .map(|entry| {
+            let client = accounts.client(&entry.account).ok_or_else(|| {
+                ImportError::UnmappedAccount(entry.account.clone(), entry.reference.clone())
+            })?;
+            let r#type = if entry.credit {
+                TransactionType::Deposit
+            } else {
+                TransactionType::Withdrawal
+            };
+
+            let tx = Transaction::tx_for_reference(&entry.reference);
+            let mut transaction = Transaction::new(r#type, client.clone(), tx, Some(entry.amount))
+                .with_source(Arc::from(entry.statement))
+                .with_memo(entry.reference)
+                .from_bank_statement();
+            if let Some(currency) = entry.currency {
+                transaction = transaction.with_currency(currency);
+            }
+            if let Some(date) = entry.booking_date {
+                transaction = transaction.with_date(date);
+            }
+
+            Ok(transaction)
+        })
+        .collect::<Result<_, _>>()?;
+
+    // A different reference under the same tx id is a hash collision, which
+    // would otherwise be mistaken for a duplicate entry.
+    let mut references: HashMap<TransactionKey, &str> = HashMap::new();
+    for transaction in transactions.iter() {
+        if let Some(reference) = transaction.memo() {
+            match references.insert(transaction.key(), reference) {
+                Some(other) if other != reference => {
+                    return Err(ImportError::ReferenceCollision(
+                        String::from(other),
+                        String::from(reference),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(transactions)
+}
+
+/// Attempts to import the booked entries of the camt.053 file located at the
+/// provided PathBuf.
+pub fn import_file(
+    path: PathBuf,
+    accounts: &BankAccounts,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let xml = std::fs::read_to_string(path)?;
+    Ok(import(&xml, accounts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ClientId;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>MSG-1</MsgId></GrpHdr>
+    <Stmt>
+      <Id>STMT-2024-001</Id>
+      <Acct><Id><IBAN>DE89 3704 0044 0532 0130 00</IBAN></Id></Acct>
+      <!-- Booked credit -->
+      <Ntry>
+        <NtryRef>REF-1</NtryRef>
+        <Amt Ccy="EUR">100.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-01</Dt></BookgDt>
+        <AddtlNtryInf>Payment from Smith &amp; Sons</AddtlNtryInf>
+      </Ntry>
+      <Ntry>
+        <AcctSvcrRef>REF-2</AcctSvcrRef>
+        <Amt Ccy="EUR">20</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2024-03-02T10:00:00</DtTm></BookgDt>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn should_import_booked_entries() {
+        let accounts =
+            BankAccounts::from_reader("account,client\nDE89370400440532013000,7\n".as_bytes())
+                .unwrap();
+        let transactions = import(STATEMENT, &accounts).unwrap();
+
+        // The pending entry is skipped.
+        let eur: Currency = "EUR".parse().unwrap();
+        assert_eq!(
+            transactions,
+            [
+                Transaction::new(
+                    TransactionType::Deposit,
+                    ClientId::Numeric(7),
+                    Transaction::tx_for_reference("REF-1"),
+                    Some(100.5)
+                )
+                .with_source(Arc::from("STMT-2024-001"))
+                .with_currency(eur)
+                .with_date(Date::new(2024, 3, 1).unwrap())
+                .with_memo(String::from("REF-1"))
+                .from_bank_statement(),
+                Transaction::new(
+                    TransactionType::Withdrawal,
+                    ClientId::Numeric(7),
+                    Transaction::tx_for_reference("REF-2"),
+                    Some(20.0)
+                )
+                .with_source(Arc::from("STMT-2024-001"))
+                .with_currency(eur)
+                .with_date(Date::new(2024, 3, 2).unwrap())
+                .with_memo(String::from("REF-2"))
+                .from_bank_statement(),
+            ]
+        );
+
+        // The tx ids follow the entries' references rather than their
+        // positions, so a statement with its entries reordered imports the
+        // same transactions.
+        let reordered = r#"<Document><BkToCstmrStmt><Stmt>
+  <Id>STMT-2024-001</Id>
+  <Acct><Id><IBAN>DE89 3704 0044 0532 0130 00</IBAN></Id></Acct>
+  <Ntry>
+    <AcctSvcrRef>REF-2</AcctSvcrRef><Amt Ccy="EUR">20</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+    <Sts>BOOK</Sts><BookgDt><Dt>2024-03-02</Dt></BookgDt>
+  </Ntry>
+  <Ntry>
+    <NtryRef>REF-1</NtryRef><Amt Ccy="EUR">100.50</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+    <Sts>BOOK</Sts><BookgDt><Dt>2024-03-01</Dt></BookgDt>
+  </Ntry>
+</Stmt></BkToCstmrStmt></Document>"#;
+        let mut reimported = import(reordered, &accounts).unwrap();
+        reimported.reverse();
+        assert_eq!(reimported, transactions);
+
+        // Entries of unmapped accounts are rejected.
+        assert_eq!(
+            import(STATEMENT, &BankAccounts::default()).unwrap_err(),
+            ImportError::UnmappedAccount(
+                String::from("DE89 3704 0044 0532 0130 00"),
+                String::from("REF-1")
+            )
+        );
+
+        // Entries whose references hash to the same tx id are rejected
+        // rather than dropped as duplicates.
+        assert_eq!(
+            Transaction::tx_for_reference("REF-462789"),
+            Transaction::tx_for_reference("REF-679192")
+        );
+        let colliding = STATEMENT
+            .replace("REF-1", "REF-462789")
+            .replace("REF-2", "REF-679192");
+        assert_eq!(
+            import(&colliding, &accounts).unwrap_err(),
+            ImportError::ReferenceCollision(String::from("REF-462789"), String::from("REF-679192"))
+        );
+
+        // Malformed documents are rejected.
+        assert!(matches!(
+            import("<Document><BkToCstmrStmt></Document>", &accounts),
+            Err(ImportError::Malformed(_))
+        ));
+    }
+}
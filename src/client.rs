@@ -0,0 +1,190 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::Infallible;
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Identifies a client. Clients are identified by a number, or by an opaque
+/// string such as a UUID assigned by a partner. Ids written as a canonical
+/// decimal number are numeric, and are compared and ordered numerically before
+/// opaque ids.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClientId {
+    Numeric(u64),
+
+    /// An opaque id, which is shared rather than copied between the
+    /// transactions, accounts and records of a client.
+    Opaque(Arc<str>),
+}
+
+/// The id of the house account, which fees and spread revenue are credited to
+/// unless another account is designated. Input can't refer to it, since the id
+/// is refused when client ids are deserialized, and the Ledger rejects input
+/// rows for a designated house account.
+pub const HOUSE_ACCOUNT: &str = "house";
+
+impl ClientId {
+    /// Returns the reserved id of the house account.
+    pub fn house() -> Self {
+        ClientId::Opaque(Arc::from(HOUSE_ACCOUNT))
+    }
+}
+
+impl Default for ClientId {
+    fn default() -> Self {
+        ClientId::Numeric(0)
+    }
+}
+
+impl From<u64> for ClientId {
+    fn from(id: u64) -> Self {
+        ClientId::Numeric(id)
+    }
+}
+
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        ClientId::Numeric(u64::from(id))
+    }
+}
+
+impl From<&str> for ClientId {
+    fn from(id: &str) -> Self {
+        // Only canonical decimal numbers are numeric, so that ids such as
+        // "007" or "+7" are kept exactly as they were read.
+        let canonical = !id.is_empty()
+            && id.bytes().all(|b| b.is_ascii_digit())
+            && (id == "0" || !id.starts_with('0'));
+        match id.parse::<u64>() {
+            Ok(numeric) if canonical => ClientId::Numeric(numeric),
+            _ => ClientId::Opaque(Arc::from(id)),
+        }
+    }
+}
+
+impl FromStr for ClientId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ClientId::from(s))
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientId::Numeric(id) => write!(f, "{}", id),
+            ClientId::Opaque(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl Serialize for ClientId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ClientId::Numeric(id) => serializer.serialize_u64(*id),
+            ClientId::Opaque(id) => serializer.serialize_str(id),
+        }
+    }
+}
+
+struct ClientIdVisitor;
+
+impl Visitor<'_> for ClientIdVisitor {
+    type Value = ClientId;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a numeric or string client id")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(ClientId::Numeric(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        match u64::try_from(v) {
+            Ok(id) => Ok(ClientId::Numeric(id)),
+            Err(_) => Ok(ClientId::Opaque(Arc::from(v.to_string()))),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if v.is_empty() {
+            return Err(E::custom("empty client id"));
+        }
+        if v == HOUSE_ACCOUNT {
+            return Err(E::custom(format!(
+                "client id {} is reserved for the house account",
+                v
+            )));
+        }
+        Ok(ClientId::from(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Ids are read as strings and classified by `From<&str>`, rather than
+        // letting the format infer a type, so that ids such as "5e3" or
+        // "true" are opaque.
+        deserializer.deserialize_str(ClientIdVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_numeric_and_opaque_ids() {
+        assert_eq!(ClientId::from("42"), ClientId::Numeric(42));
+        assert_eq!(
+            ClientId::from("18446744073709551615"),
+            ClientId::Numeric(u64::MAX)
+        );
+
+        // Any other id is opaque and kept as it was read.
+        let uuid = "3f2b8c1e-9d4a-4e7b-8a6f-2c1d0e9b7a53";
+        assert_eq!(ClientId::from("-7").to_string(), "-7");
+        assert_eq!(ClientId::from(uuid).to_string(), uuid);
+        assert!(ClientId::from(uuid) > ClientId::Numeric(u64::MAX));
+
+        // Only canonical decimal numbers are numeric.
+        assert_eq!(ClientId::from("0"), ClientId::Numeric(0));
+        for id in ["00042", "+42", "5e3", "true", "18446744073709551616"] {
+            assert_eq!(ClientId::from(id), ClientId::Opaque(Arc::from(id)));
+            assert_eq!(ClientId::from(id).to_string(), id);
+        }
+
+        // Ids deserialize from CSV as either kind.
+        let data = format!("client\n7\n-7\n{}\n00042\n5e3\ntrue\n", uuid);
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let ids: Vec<ClientId> = rdr
+            .deserialize()
+            .map(|row: Result<(ClientId,), _>| row.unwrap().0)
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ClientId::Numeric(7),
+                ClientId::from("-7"),
+                ClientId::from(uuid),
+                ClientId::from("00042"),
+                ClientId::from("5e3"),
+                ClientId::from("true")
+            ]
+        );
+    }
+
+    #[test]
+    fn should_refuse_the_house_account_id() {
+        // The reserved id can't be read from input, while the house account
+        // can still be designated by it.
+        let mut rdr = csv::Reader::from_reader("client\nhouse\n".as_bytes());
+        let row: Option<Result<(ClientId,), _>> = rdr.deserialize().next();
+        assert!(row.unwrap().is_err());
+        assert_eq!("house".parse::<ClientId>().unwrap(), ClientId::house());
+    }
+}
@@ -0,0 +1,127 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// The number of decimal places used for currencies whose ISO 4217 minor
+/// unit isn't listed below.
+const DEFAULT_PRECISION: i32 = 4;
+
+/// Currencies which have no minor unit.
+const ZERO_DECIMAL_CURRENCIES: [&str; 16] = [
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "UYI", "VND",
+    "VUV", "XAF", "XOF",
+];
+
+/// Currencies whose minor unit has three decimal places.
+const THREE_DECIMAL_CURRENCIES: [&str; 7] = ["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// An ISO 4217 currency code. Transactions which don't specify a currency are
+/// assumed to be in the default currency, USD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub fn as_str(&self) -> &str {
+        // Currencies can only be constructed from ASCII letters.
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// Returns the number of decimal places amounts in this currency are
+    /// rounded to.
+    pub fn precision(&self) -> i32 {
+        if ZERO_DECIMAL_CURRENCIES.contains(&self.as_str()) {
+            0
+        } else if THREE_DECIMAL_CURRENCIES.contains(&self.as_str()) {
+            3
+        } else {
+            DEFAULT_PRECISION
+        }
+    }
+
+    /// Rounds the provided value to the precision of this currency.
+    pub fn round(&self, value: f64) -> f64 {
+        let factor = 10f64.powi(self.precision());
+        (value * factor).round() / factor
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency(*b"USD")
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_alphabetic) {
+            return Err(format!("invalid ISO 4217 currency code: {}", s));
+        }
+
+        let mut code = [0u8; 3];
+        for (i, b) in bytes.iter().enumerate() {
+            code[i] = b.to_ascii_uppercase();
+        }
+
+        Ok(Currency(code))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+struct CurrencyVisitor;
+
+impl Visitor<'_> for CurrencyVisitor {
+    type Value = Currency;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "an ISO 4217 currency code")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(CurrencyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_currency_codes() {
+        assert_eq!("jpy".parse::<Currency>().unwrap().to_string(), "JPY");
+        assert!("US".parse::<Currency>().is_err());
+        assert!("U$D".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn should_round_to_currency_precision() {
+        let jpy: Currency = "JPY".parse().unwrap();
+        let bhd: Currency = "BHD".parse().unwrap();
+        let usd = Currency::default();
+
+        assert_eq!(jpy.round(1234.5678), 1235.0);
+        assert_eq!(bhd.round(1.23456), 1.235);
+        assert_eq!(usd.round(8.675309), 8.6753);
+    }
+}
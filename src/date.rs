@@ -0,0 +1,129 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// A calendar date in the proleptic Gregorian calendar, written in ISO 8601
+/// form, e.g. 2021-03-14.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Attempts to create a date from its year, month and day.
+    pub fn new(year: i32, month: u32, day: u32) -> Result<Self, String> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(format!("invalid date: {:04}-{:02}-{:02}", year, month, day));
+        }
+
+        Ok(Self { year, month, day })
+    }
+
+    /// Returns the number of days between 1970-01-01 and this date.
+    pub fn days_since_epoch(&self) -> i64 {
+        // Shift the year to start in March so that the leap day is the last
+        // day of the year.
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from((self.month + 9) % 12);
+        let day_of_year = (153 * month + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146_097 + day_of_era - 719_468
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid ISO 8601 date: {}", s);
+
+        let mut parts = s.splitn(3, '-');
+        let (year, month, day) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(year), Some(month), Some(day))
+                if year.len() == 4 && month.len() == 2 && day.len() == 2 =>
+            {
+                (year, month, day)
+            }
+            _ => return Err(invalid()),
+        };
+
+        Date::new(
+            year.parse().map_err(|_| invalid())?,
+            month.parse().map_err(|_| invalid())?,
+            day.parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct DateVisitor;
+
+impl Visitor<'_> for DateVisitor {
+    type Value = Date;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "an ISO 8601 date")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(DateVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_dates() {
+        let date: Date = "2024-02-29".parse().unwrap();
+        assert_eq!(date.to_string(), "2024-02-29");
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert!("20230101".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn should_count_days_since_epoch() {
+        let epoch: Date = "1970-01-01".parse().unwrap();
+        let leap: Date = "2000-03-01".parse().unwrap();
+        let before: Date = "1969-12-31".parse().unwrap();
+
+        assert_eq!(epoch.days_since_epoch(), 0);
+        assert_eq!(leap.days_since_epoch(), 11_017);
+        assert_eq!(before.days_since_epoch(), -1);
+    }
+}
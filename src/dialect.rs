@@ -0,0 +1,258 @@
+use crate::engine::Transaction;
+use csv::{ByteRecord, ReaderBuilder, StringRecord, Trim};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// The columns of a file without a header row, in order, unless the dialect
+/// maps its positions to other columns.
+const DEFAULT_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// A single row of a dialect configuration. The name and value a setting uses
+/// depend on the setting.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct DialectSetting {
+    setting: String,
+    name: Option<String>,
+    value: Option<String>,
+}
+
+/// A Dialect describes how a partner's CSV files are laid out: the delimiter
+/// and quoting they use, whether they have a header row, what their columns
+/// are called and what their transaction types are called. It is applied to
+/// each row before the row is deserialized into a Transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dialect {
+    delimiter: u8,
+    quote: u8,
+    quoting: bool,
+    has_headers: bool,
+
+    /// Maps the partner's column names, or the 1-based positions of the
+    /// columns of a file without a header row, to the Transaction's columns.
+    columns: HashMap<String, String>,
+
+    /// Maps the partner's transaction types to the Ledger's.
+    type_aliases: HashMap<String, String>,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            has_headers: true,
+            columns: HashMap::new(),
+            type_aliases: HashMap::new(),
+        }
+    }
+}
+
+impl Dialect {
+    /// Attempts to read a dialect configuration from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut dialect = Self::default();
+        for setting in iter.deserialize() {
+            let DialectSetting {
+                setting,
+                name,
+                value,
+            } = setting?;
+            let missing = |param: &str| format!("dialect setting {} requires a {}", setting, param);
+            let value = value.ok_or_else(|| missing("value"))?;
+
+            match setting.as_str() {
+                "delimiter" => dialect.delimiter = Self::parse_byte(&setting, &value)?,
+                "quote" => dialect.quote = Self::parse_byte(&setting, &value)?,
+                "quoting" => dialect.quoting = value.parse()?,
+                "headers" => dialect.has_headers = value.parse()?,
+                "column" => {
+                    dialect
+                        .columns
+                        .insert(name.ok_or_else(|| missing("name"))?, value);
+                }
+                "type" => {
+                    dialect
+                        .type_aliases
+                        .insert(name.ok_or_else(|| missing("name"))?, value);
+                }
+                setting => return Err(format!("unknown dialect setting: {}", setting).into()),
+            }
+        }
+
+        Ok(dialect)
+    }
+
+    fn parse_byte(setting: &str, value: &str) -> Result<u8, String> {
+        match value.as_bytes() {
+            [byte] => Ok(*byte),
+            _ if value == "\\t" => Ok(b'\t'),
+            _ => Err(format!(
+                "dialect setting {} requires a single character, got {:?}",
+                setting, value
+            )),
+        }
+    }
+
+    /// Returns a CSV reader builder configured for this dialect.
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .flexible(true)
+            .has_headers(self.has_headers)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .quoting(self.quoting)
+            .trim(Trim::All);
+        builder
+    }
+
+    /// Attempts to read the header row of the provided reader, or the
+    /// positions of a file without one, and returns it with each column
+    /// renamed to the Transaction column it maps to.
+    pub fn headers<R: io::Read>(
+        &self,
+        rdr: &mut csv::Reader<R>,
+    ) -> Result<StringRecord, csv::Error> {
+        if self.has_headers {
+            return Ok(rdr
+                .headers()?
+                .iter()
+                .map(|column| self.columns.get(column).map_or(column, String::as_str))
+                .collect());
+        }
+
+        let len = self
+            .columns
+            .keys()
+            .filter_map(|position| position.parse::<usize>().ok())
+            .max()
+            .unwrap_or_default()
+            .max(DEFAULT_COLUMNS.len());
+        Ok((1..=len)
+            .map(|position| match self.columns.get(&position.to_string()) {
+                Some(column) => column.as_str(),
+                None => DEFAULT_COLUMNS
+                    .get(position - 1)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Returns the Ledger's name for the provided transaction type of the
+    /// partner.
+    pub fn translate_type<'a>(&'a self, r#type: &'a str) -> &'a str {
+        self.type_aliases.get(r#type).map_or(r#type, String::as_str)
+    }
+
+    /// Attempts to deserialize a Transaction from a record read with this
+    /// dialect, translating its transaction type.
+    pub fn deserialize(
+        &self,
+        record: &ByteRecord,
+        headers: &StringRecord,
+    ) -> Result<Transaction, csv::Error> {
+        let headers = headers.as_byte_record();
+        if self.type_aliases.is_empty() {
+            return record.deserialize(Some(headers));
+        }
+
+        let index = headers.iter().position(|column| column == b"type");
+        let alias = index
+            .and_then(|index| record.get(index))
+            .and_then(|r#type| std::str::from_utf8(r#type).ok())
+            .and_then(|r#type| self.type_aliases.get(r#type));
+
+        match (index, alias) {
+            (Some(index), Some(alias)) => {
+                let mut translated: ByteRecord = record
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| if i == index { alias.as_bytes() } else { field })
+                    .collect();
+                translated.set_position(record.position().cloned());
+                translated.deserialize(Some(headers))
+            }
+            _ => record.deserialize(Some(headers)),
+        }
+    }
+}
+
+impl TryFrom<PathBuf> for Dialect {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a dialect configuration from the CSV file located at
+    /// the provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TransactionType;
+
+    fn read(dialect: &Dialect, data: &str) -> Vec<Transaction> {
+        let mut rdr = dialect.reader_builder().from_reader(data.as_bytes());
+        let headers = dialect.headers(&mut rdr).unwrap();
+        rdr.byte_records()
+            .map(|record| dialect.deserialize(&record.unwrap(), &headers).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn should_map_partner_dialects() {
+        let dialect = Dialect::from_reader(
+            "setting,name,value\n\
+             delimiter,,;\n\
+             column,txn_id,tx\n\
+             column,customer,client\n\
+             type,credit,deposit\n\
+             type,debit,withdrawal\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let transactions = read(
+            &dialect,
+            "type;customer;txn_id;amount\n\
+             credit;1;1;10.0\n\
+             debit;1;2;4.0\n",
+        );
+        assert_eq!(
+            transactions[0].transaction_type(),
+            &TransactionType::Deposit
+        );
+        assert_eq!(
+            transactions[1].transaction_type(),
+            &TransactionType::Withdrawal
+        );
+        assert_eq!(transactions[1].tx(), 2);
+        assert_eq!(transactions[1].amount(), Some(4.0));
+
+        // Files without a header row are read by position.
+        let dialect = Dialect::from_reader(
+            "setting,name,value\n\
+             headers,,false\n\
+             column,1,client\n\
+             column,2,type\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let transactions = read(&dialect, "7,deposit,3,1.5\n");
+        assert_eq!(transactions[0].client().to_string(), "7");
+        assert_eq!(transactions[0].tx(), 3);
+
+        // Unknown settings are rejected.
+        assert!(Dialect::from_reader("setting,name,value\nescape,,\\\n".as_bytes()).is_err());
+    }
+}
@@ -1,17 +1,34 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::dialect::Dialect;
 use crate::errors::{
-    AccountError, ChargebackError, DepositError, DisputeError, DuplicateTransactionError,
+    AccountError, AccrualError, ChargebackError, CreditLimitError, DepositError, DisputeError,
+    DuplicateTransactionError, ExchangeError, HouseAccountError, InvariantViolation, RefundError,
     ResolveError, StatementError, WithdrawalError,
 };
+use crate::fees::{FeeRecord, FeeSchedule};
+use crate::fx::{ExchangeRecord, RateTable};
+use crate::ingest::{BatchRecord, DuplicateRecord, OrphanRecord, PartnerWarning, WarningRecord};
+use crate::interest::{AccrualRecord, InterestSchedule, ACCRUAL_SOURCE};
+use crate::journal::{Journal, LedgerAccount};
+use crate::limits::{RecentWithdrawal, WithdrawalLimits};
+use crate::monitoring::{ChargebackPolicy, ClientMetrics, MonitoringRecord};
+use crate::mt940::{Mt940Balance, Mt940Statement, ReconciliationRecord};
+use crate::registry::{BankAccounts, ClientRecord, ClientRegistry, ClientStatus};
+use crate::risk::{ClientActivity, RiskAction, RiskRules};
+use crate::screening::{Blocklist, QuarantineRecord};
 use csv::Trim;
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::{Entry, OccupiedEntry};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::error::Error;
+use std::io;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
-const PRECISION: f64 = 10_000.0;
-
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Hash, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     /// A deposit is a credit to the client's asset account, meaning it should
@@ -42,21 +59,277 @@ pub enum TransactionType {
     /// reversing a transaction. Funds that were held have now been withdrawn.
     /// This means that the clients held funds and total funds should decrease
     /// by the amount previously disputed. If a chargeback occurs the client's
-    /// account should be immediately frozen.
+    /// account is frozen, or placed under review if the Ledger's
+    /// ChargebackPolicy tolerates it.
     Chargeback,
+
+    /// A credit limit is an administrative transaction which sets the
+    /// approved credit line of the client's account to the specified amount,
+    /// allowing withdrawals to drive the client's available funds negative
+    /// down to the negative of that amount.
+    #[serde(rename = "credit_limit")]
+    CreditLimit,
+
+    /// An accrue is a control transaction which posts interest on the
+    /// available funds of every unlocked account. Its amount is the number of
+    /// days in the accrual period, and the interest for each account is
+    /// posted as a generated deposit with its own tx id.
+    Accrue,
+
+    /// A refund is a merchant-initiated reversal of an earlier deposit. Like
+    /// a dispute it references the original deposit by ID, but it reverses
+    /// the funds immediately without locking the client's account. This means
+    /// that the clients available and total funds should decrease by the
+    /// amount refunded.
+    Refund,
+
+    /// An exchange converts funds between two currencies on the same client
+    /// account. The amount is debited from the client's balance in the
+    /// transaction's currency and credited, at the exchange rate effective on
+    /// the transaction's date less the spread, to their balance in the
+    /// currency being exchanged to.
+    Exchange,
+}
+
+/// Determines how the Ledger handles a dispute that would drive a client's
+/// available funds below zero, e.g. when the disputed deposit has already
+/// been withdrawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NegativeBalancePolicy {
+    /// The dispute is rejected with an error and no funds are held.
+    Reject,
+
+    /// The full disputed amount is held, allowing the client's available
+    /// funds to go negative. The account is reported as having a negative
+    /// balance along with the amount the client owes.
+    #[default]
+    AllowDebt,
+
+    /// Only the funds that are currently available are held, so the client's
+    /// available funds never go below zero.
+    HoldAvailable,
+}
+
+impl FromStr for NegativeBalancePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(NegativeBalancePolicy::Reject),
+            "allow-debt" => Ok(NegativeBalancePolicy::AllowDebt),
+            "hold-available" => Ok(NegativeBalancePolicy::HoldAvailable),
+            _ => Err(format!("unknown negative balance policy: {}", s)),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct Transaction {
     r#type: TransactionType,
-    client: u16,
+    client: ClientId,
     tx: u32,
     amount: Option<f64>,
+
+    /// The ISO 4217 currency of the transaction, or the default currency if
+    /// none was specified.
+    #[serde(default)]
+    currency: Option<Currency>,
+
+    /// The ISO 4217 currency an exchange converts funds to.
+    #[serde(default)]
+    to_currency: Option<Currency>,
+
+    /// The date the transaction took place, used to find the effective
+    /// exchange rate.
+    #[serde(default)]
+    date: Option<Date>,
+
+    /// The partner or input file the transaction was ingested from. Tx ids
+    /// are only unique within their source.
+    #[serde(default)]
+    source: Option<Arc<str>>,
+
+    /// The batch the transaction belongs to. Consecutive rows of the same
+    /// batch are applied all-or-nothing.
+    #[serde(default)]
+    batch: Option<Arc<str>>,
+
+    /// A free-text description of the transaction, e.g. the narrative of a
+    /// bank statement line.
+    #[serde(default)]
+    memo: Option<String>,
+
+    /// Whether the transaction was imported from a bank statement, rather
+    /// than read from a partner's input.
+    #[serde(skip)]
+    bank_statement: bool,
+}
+
+/// Identifies a transaction by its tx id within the namespace of its source,
+/// since the tx ids of different partners may collide.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TransactionKey {
+    pub source: Option<Arc<str>>,
+    pub tx: u32,
+}
+
+/// Identifies an applied dispute, resolve or chargeback among those of the
+/// current dispute of the transaction they reference, whose key they share,
+/// by their type and amount.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ReferenceKey {
+    r#type: TransactionType,
+    amount: Option<u64>,
+}
+
+impl Transaction {
+    /// Creates a transaction which wasn't read from a CSV file, e.g. one
+    /// imported from a bank statement.
+    pub fn new(r#type: TransactionType, client: ClientId, tx: u32, amount: Option<f64>) -> Self {
+        Self {
+            r#type,
+            client,
+            tx,
+            amount,
+            currency: None,
+            to_currency: None,
+            date: None,
+            source: None,
+            batch: None,
+            memo: None,
+            bank_statement: false,
+        }
+    }
+
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = Some(currency);
+        self
+    }
+
+    pub fn with_to_currency(mut self, currency: Currency) -> Self {
+        self.to_currency = Some(currency);
+        self
+    }
+
+    pub fn with_date(mut self, date: Date) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    pub fn with_source(mut self, source: Arc<str>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_memo(mut self, memo: String) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    /// Marks the transaction as imported from a bank statement.
+    pub fn from_bank_statement(mut self) -> Self {
+        self.bank_statement = true;
+        self
+    }
+
+    /// Returns the tx id of a transaction imported from a bank statement,
+    /// derived from the bank's reference for it with the 32-bit FNV-1a hash,
+    /// so that it stays the same however the statement's entries are
+    /// ordered.
+    pub fn tx_for_reference(reference: &str) -> u32 {
+        reference.bytes().fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+    }
+
+    pub fn transaction_type(&self) -> &TransactionType {
+        &self.r#type
+    }
+
+    pub fn client(&self) -> &ClientId {
+        &self.client
+    }
+
+    pub fn tx(&self) -> u32 {
+        self.tx
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Returns the key the transaction is stored under, and which
+    /// dispute-family transactions referencing it are resolved by.
+    pub fn key(&self) -> TransactionKey {
+        TransactionKey {
+            source: self.source.clone(),
+            tx: self.tx,
+        }
+    }
+
+    /// Returns the key an applied dispute, resolve or chargeback is recorded
+    /// under, so that re-sent rows are recognized.
+    fn reference_key(&self) -> ReferenceKey {
+        ReferenceKey {
+            r#type: self.r#type.clone(),
+            amount: self.amount.map(f64::to_bits),
+        }
+    }
+
+    /// Returns whether the transaction is a dispute, resolve or chargeback,
+    /// which references another transaction by its key.
+    fn is_reference(&self) -> bool {
+        matches!(
+            self.r#type,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        )
+    }
+
+    pub fn amount(&self) -> Option<f64> {
+        self.amount
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency.unwrap_or_default()
+    }
+
+    pub fn date(&self) -> Option<Date> {
+        self.date
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Account {
-    client: u16,
+    client: ClientId,
+
+    /// The balances of the account, one per currency the client holds funds
+    /// in.
+    balances: BTreeMap<Currency, Balance>,
+
+    /// Whether the account is locked. An account is locked once its
+    /// chargebacks exceed the thresholds of the Ledger's ChargebackPolicy.
+    locked: bool,
+
+    /// Whether the account is under review because it has had chargebacks
+    /// below the thresholds which lock it.
+    review: bool,
+
+    /// The approved credit line of the account, in the default currency.
+    /// Withdrawals in the default currency may drive the available funds
+    /// negative down to the negative of this amount.
+    credit_limit: f64,
+}
+
+/// The balance of a client's account in a single currency. Balances are
+/// derived from the client's liability and suspense accounts in the Ledger's
+/// journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Balance {
+    currency: Currency,
 
     /// The total funds that are available for trading, staking, withdrawal,
     /// etc.
@@ -67,21 +340,184 @@ pub struct Account {
 
     /// The total funds that are available or held.
     total: f64,
+}
+
+/// Per-account configuration which is applied to client accounts as they're
+/// created, typically loaded from an account configuration CSV file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AccountConfig {
+    client: ClientId,
+
+    /// The approved credit line of the account.
+    #[serde(default)]
+    credit_limit: f64,
+
+    /// The tier of the account, which determines its withdrawal limits.
+    #[serde(default)]
+    tier: Option<String>,
+}
 
-    /// Whether the account is locked. An account is locked if a charge back
-    /// occurs.
+/// A single row of the account statement report. Each row describes the
+/// balance of a client's account in a single currency.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Statement {
+    client: ClientId,
+    #[serde(default)]
+    currency: Currency,
+    available: f64,
+    held: f64,
+    total: f64,
     locked: bool,
+
+    /// Whether the account is under review because of chargebacks.
+    #[serde(default)]
+    review: bool,
+
+    /// Whether the client's available funds are below zero, e.g. because a
+    /// deposit was disputed after the funds had already been withdrawn.
+    #[serde(default)]
+    negative_balance: bool,
+
+    /// The amount the client owes when their available funds are negative.
+    #[serde(default)]
+    owed: f64,
+
+    /// The approved credit line of the account.
+    #[serde(default)]
+    credit_limit: f64,
+
+    /// The amount of the approved credit line that is currently in use.
+    #[serde(default)]
+    credit_used: f64,
+}
+
+/// The client's name, tier, status and opening date from the Ledger's client
+/// registry, which are appended to each statement row when a registry is
+/// configured. They are empty if the client isn't registered.
+#[derive(Debug, Default, Serialize)]
+struct Registration {
+    name: Option<String>,
+    tier: Option<String>,
+    status: Option<ClientStatus>,
+    opened: Option<Date>,
+}
+
+impl Registration {
+    fn new(record: Option<&ClientRecord>) -> Self {
+        match record {
+            Some(record) => Self {
+                name: record.name.clone(),
+                tier: record.tier.clone(),
+                status: Some(record.status()),
+                opened: record.opened,
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+impl Statement {
+    fn new(account: &Account, balance: &Balance) -> Self {
+        let currency = balance.currency;
+        let credit_limit = account.credit_limit_for(currency);
+        let owed = if balance.available < 0.0 {
+            currency.round(-balance.available)
+        } else {
+            0.0
+        };
+        Self {
+            client: account.client.clone(),
+            currency,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total,
+            locked: account.locked,
+            review: account.review,
+            negative_balance: balance.available < 0.0,
+            owed,
+            credit_limit,
+            credit_used: owed.min(credit_limit),
+        }
+    }
+
+    /// Verifies the ledger invariants for this statement row:
+    ///
+    /// - total funds MUST equal available funds plus held funds.
+    /// - held funds MUST NOT be negative.
+    /// - total funds MUST NOT be more negative than the account's credit
+    ///   limit unless the NegativeBalancePolicy allows clients to go into
+    ///   debt.
+    pub fn check_invariants(
+        &self,
+        policy: NegativeBalancePolicy,
+    ) -> Result<(), InvariantViolation> {
+        let currency = self.currency;
+        if currency.round(self.available + self.held) != currency.round(self.total) {
+            return Err(InvariantViolation::TotalMismatch(
+                self.client.clone(),
+                self.available,
+                self.held,
+                self.total,
+            ));
+        }
+
+        if self.held < 0.0 {
+            return Err(InvariantViolation::NegativeHeld(
+                self.client.clone(),
+                self.held,
+            ));
+        }
+
+        if self.total < -self.credit_limit && policy != NegativeBalancePolicy::AllowDebt {
+            return Err(InvariantViolation::NegativeTotal(
+                self.client.clone(),
+                self.total,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracks the portions of a stored transaction that have been disputed or
+/// reversed.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TransactionState {
+    /// The amount of the transaction that is currently under dispute and
+    /// held on the client's account.
+    disputed: f64,
+
+    /// The amount of the transaction that has already been charged back.
+    charged_back: f64,
+
+    /// The amount of the transaction that has already been refunded.
+    refunded: f64,
+
+    /// The fee that was charged for the transaction.
+    fee: f64,
+}
+
+impl TransactionState {
+    /// Returns the amount of the original transaction that may still be
+    /// disputed or refunded, i.e. the original amount minus anything that is
+    /// already under dispute, has been charged back, or has been refunded.
+    fn remaining(&self, original: f64, currency: Currency) -> f64 {
+        currency.round(original - self.disputed - self.charged_back - self.refunded)
+    }
+
+    fn is_disputed(&self) -> bool {
+        self.disputed > 0.0
+    }
 }
 
 impl Account {
-    pub fn new_account(client: u16, balance: f64) -> Self {
-        let balance = Self::round(balance);
+    pub fn new_account(client: ClientId) -> Self {
         Self {
             client,
-            available: balance,
-            held: 0.0,
-            total: balance,
+            balances: BTreeMap::new(),
             locked: false,
+            review: false,
+            credit_limit: 0.0,
         }
     }
 
@@ -89,36 +525,367 @@ impl Account {
         self.locked = true;
     }
 
-    pub fn deposit_funds(&mut self, amount: f64) {
-        self.available = Self::round(self.available + amount);
-        self.total = Self::round(self.calculate_total());
+    /// Returns the account's balance in the provided currency, if the client
+    /// has ever held funds in it.
+    pub fn balance(&self, currency: Currency) -> Option<&Balance> {
+        self.balances.get(&currency)
+    }
+
+    /// Returns the account's balance in the provided currency, opening an
+    /// empty balance if the client has never held funds in it.
+    pub fn balance_mut(&mut self, currency: Currency) -> &mut Balance {
+        self.balances
+            .entry(currency)
+            .or_insert_with(|| Balance::new(currency))
+    }
+
+    /// Returns the approved credit line of the account in the provided
+    /// currency. Credit lines are only extended in the default currency.
+    pub fn credit_limit_for(&self, currency: Currency) -> f64 {
+        if currency == Currency::default() {
+            self.credit_limit
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns one statement row per currency the account holds funds in.
+    pub fn statements(&self) -> impl Iterator<Item = Statement> + '_ {
+        self.balances
+            .values()
+            .map(move |balance| Statement::new(self, balance))
     }
 
-    pub fn withdraw_funds(&mut self, amount: f64) {
-        self.available = Self::round(self.available - amount);
-        self.total = Self::round(self.calculate_total());
+    /// Verifies that the account's balances are internally consistent.
+    pub fn check_invariants(
+        &self,
+        policy: NegativeBalancePolicy,
+    ) -> Result<(), InvariantViolation> {
+        for statement in self.statements() {
+            statement.check_invariants(policy)?;
+        }
+
+        Ok(())
     }
+}
 
-    fn calculate_total(&self) -> f64 {
-        self.available - self.held
+impl Balance {
+    pub fn new(currency: Currency) -> Self {
+        Self {
+            currency,
+            available: 0.0,
+            held: 0.0,
+            total: 0.0,
+        }
     }
 
-    fn round(value: f64) -> f64 {
-        (value * PRECISION).round() / PRECISION
+    /// Updates the balance to the available and held funds derived from
+    /// the Ledger's journal.
+    fn update(&mut self, available: f64, held: f64) {
+        self.available = available;
+        self.held = held;
+        self.total = self.currency.round(available + held);
     }
 }
 
 /// A Ledger is responsible for processing a collection of Transactions and
 /// tracking information about accounts, their balances, as well as any
 /// disputes, resolutions, and chargebacks to those transactions.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Ledger {
-    accounts: HashMap<u16, Account>,
-    transactions: HashMap<u32, Transaction>,
-    disputed_transactions: HashSet<u32>,
+    accounts: HashMap<ClientId, Account>,
+    transactions: HashMap<TransactionKey, Transaction>,
+
+    /// The disputes, resolves and chargebacks applied to each transaction
+    /// since its current dispute was opened.
+    applied_references: HashMap<TransactionKey, HashSet<ReferenceKey>>,
+    transaction_states: HashMap<TransactionKey, TransactionState>,
+    account_configs: HashMap<ClientId, AccountConfig>,
+    negative_balance_policy: NegativeBalancePolicy,
+    verify: bool,
+    fee_schedule: FeeSchedule,
+
+    /// The account fees and spread revenue are credited to, or the reserved
+    /// house id if none was designated.
+    house_account: Option<ClientId>,
+    fee_records: Vec<FeeRecord>,
+    interest_schedule: InterestSchedule,
+    accrual_records: Vec<AccrualRecord>,
+    generated_transactions: u32,
+    exchange_rates: RateTable,
+
+    /// The date of the last processed row that had one. Undated exchanges use
+    /// the rate effective on it, so they never use a rate dated after the
+    /// rest of the input.
+    last_date: Option<Date>,
+    exchange_records: Vec<ExchangeRecord>,
+    journal: Journal,
+    withdrawal_limits: WithdrawalLimits,
+    recent_withdrawals: HashMap<(ClientId, Currency), VecDeque<RecentWithdrawal>>,
+    rows: u64,
+    risk_rules: RiskRules,
+    held_transactions: Vec<Transaction>,
+    blocklist: Blocklist,
+    quarantine: Vec<QuarantineRecord>,
+    chargeback_policy: ChargebackPolicy,
+    client_metrics: HashMap<ClientId, ClientMetrics>,
+    registry: Option<ClientRegistry>,
+    strict_registry: bool,
+    duplicates: Vec<DuplicateRecord>,
+    atomic_files: bool,
+    in_batch: bool,
+
+    /// The state changed by the open batch, which it is rolled back to if it
+    /// fails.
+    undo: Option<UndoLog>,
+    batches: Vec<BatchRecord>,
+    reorder_window: u64,
+    orphans: VecDeque<(u64, Transaction)>,
+    expired_orphans: Vec<OrphanRecord>,
+    warnings: Vec<WarningRecord>,
+    dialect: Dialect,
+    reconciliations: Vec<ReconciliationRecord>,
+}
+
+/// A batch of input rows that is being applied all-or-nothing.
+struct Batch {
+    source: Option<Arc<str>>,
+    name: Option<Arc<str>>,
+    first_row: u64,
+    failure: Option<String>,
+}
+
+/// The state of the Ledger before a batch began, limited to what the batch
+/// has changed so that a failed batch can be rolled back without copying the
+/// whole Ledger. Entries keyed by client or transaction hold their value from
+/// before the batch first touched them, or None if they didn't exist, and the
+/// Ledger's append-only records are truncated to their length at the start.
+#[derive(Clone, Default)]
+struct UndoLog {
+    accounts: HashMap<ClientId, Option<Account>>,
+    account_configs: HashMap<ClientId, Option<AccountConfig>>,
+    client_metrics: HashMap<ClientId, Option<ClientMetrics>>,
+    activity: HashMap<ClientId, Option<ClientActivity>>,
+    transaction_states: HashMap<TransactionKey, Option<TransactionState>>,
+    recent_withdrawals: HashMap<(ClientId, Currency), Option<VecDeque<RecentWithdrawal>>>,
+    transactions: Vec<TransactionKey>,
+    references: HashMap<TransactionKey, Option<HashSet<ReferenceKey>>>,
+    orphans: VecDeque<(u64, Transaction)>,
+    journal: usize,
+    fee_records: usize,
+    accrual_records: usize,
+    exchange_records: usize,
+    held_transactions: usize,
+    quarantine: usize,
+    duplicates: usize,
+    expired_orphans: usize,
+    warnings: usize,
+    alerts: usize,
+    generated_transactions: u32,
+    last_date: Option<Date>,
+}
+
+/// Saves the value of the provided key in the undo log, unless it was
+/// already saved.
+fn save<K: Clone + Eq + std::hash::Hash, V: Clone>(
+    saved: &mut HashMap<K, Option<V>>,
+    map: &HashMap<K, V>,
+    key: &K,
+) {
+    if !saved.contains_key(key) {
+        saved.insert(key.clone(), map.get(key).cloned());
+    }
+}
+
+/// Restores the values saved in the undo log, removing the keys which didn't
+/// exist before.
+fn restore<K: Eq + std::hash::Hash, V>(map: &mut HashMap<K, V>, saved: HashMap<K, Option<V>>) {
+    for (key, value) in saved {
+        match value {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
 }
 
 impl Ledger {
+    /// Sets the policy used when a dispute would drive a client's available
+    /// funds below zero.
+    pub fn with_negative_balance_policy(mut self, policy: NegativeBalancePolicy) -> Self {
+        self.negative_balance_policy = policy;
+        self
+    }
+
+    /// Enables checking the ledger invariants of each account after every
+    /// transaction is processed. Invariants are always checked in debug
+    /// builds.
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Sets the schedule of fees charged for each transaction. Fees are
+    /// credited to the designated house account.
+    pub fn with_fee_schedule(mut self, schedule: FeeSchedule, house_account: ClientId) -> Self {
+        self.fee_schedule = schedule;
+        self.house_account = Some(house_account);
+        self
+    }
+
+    /// Sets the schedule of interest rates used when accruing interest.
+    pub fn with_interest_schedule(mut self, schedule: InterestSchedule) -> Self {
+        self.interest_schedule = schedule;
+        self
+    }
+
+    /// Sets the table of exchange rates used when exchanging funds between
+    /// currencies. Spread revenue is credited to the house account.
+    pub fn with_exchange_rates(mut self, rates: RateTable) -> Self {
+        self.exchange_rates = rates;
+        self
+    }
+
+    /// Sets the withdrawal limits enforced for each account tier.
+    pub fn with_withdrawal_limits(mut self, limits: WithdrawalLimits) -> Self {
+        self.withdrawal_limits = limits;
+        self
+    }
+
+    /// Sets the risk rules evaluated before each transaction is applied.
+    pub fn with_risk_rules(mut self, rules: RiskRules) -> Self {
+        self.risk_rules = rules;
+        self
+    }
+
+    /// Sets the policy which determines when chargebacks lock a client's
+    /// account.
+    pub fn with_chargeback_policy(mut self, policy: ChargebackPolicy) -> Self {
+        self.chargeback_policy = policy;
+        self
+    }
+
+    /// Sets whether each input file is applied as a single batch, which is
+    /// rolled back as a whole if any of its rows fail.
+    pub fn with_atomic_files(mut self, atomic: bool) -> Self {
+        self.atomic_files = atomic;
+        self
+    }
+
+    /// Sets the number of rows a dispute, resolve or chargeback referencing
+    /// an unknown transaction is parked for, waiting for the transaction to
+    /// arrive, before it expires. Such rows are ignored if the window is
+    /// zero, which is the default.
+    pub fn with_reorder_window(mut self, rows: u64) -> Self {
+        self.reorder_window = rows;
+        self
+    }
+
+    /// Sets the CSV dialect input files are read with.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Sets the blocklist clients are screened against before their accounts
+    /// are created or credited.
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /// Sets the registry of known clients. The tier and credit line of each
+    /// registered client are applied to their account as it's created, and
+    /// the accounts of suspended or closed clients are created locked. In
+    /// strict mode, accounts are only created for registered clients.
+    pub fn with_client_registry(mut self, registry: ClientRegistry, strict: bool) -> Self {
+        for record in registry.records() {
+            let config = self
+                .account_configs
+                .entry(record.client.clone())
+                .or_insert_with(|| AccountConfig {
+                    client: record.client.clone(),
+                    ..Default::default()
+                });
+            if let Some(limit) = record.credit_limit {
+                config.credit_limit = limit;
+            }
+            if record.tier.is_some() {
+                config.tier = record.tier.clone();
+            }
+        }
+        self.registry = Some(registry);
+        self.strict_registry = strict;
+        self
+    }
+
+    /// Attempts to load per-account configuration from the CSV file located
+    /// at the provided PathBuf. The configuration is applied to existing
+    /// accounts immediately and to new accounts as they're created.
+    pub fn load_account_config(&mut self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        self.load_account_config_from_reader(std::fs::File::open(path)?)
+    }
+
+    /// Attempts to load per-account configuration from the provided CSV
+    /// reader. The client registry takes precedence for the tier and credit
+    /// line of registered clients, so the configuration only fills the ones
+    /// the registry doesn't set.
+    pub fn load_account_config_from_reader<R: io::Read>(
+        &mut self,
+        rdr: R,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        for config in iter.deserialize() {
+            let mut config: AccountConfig = config?;
+            if let Some(record) = self.registered(&config.client) {
+                if let Some(limit) = record.credit_limit {
+                    config.credit_limit = limit;
+                }
+                if record.tier.is_some() {
+                    config.tier = record.tier.clone();
+                }
+            }
+            if let Some(account) = self.accounts.get_mut(&config.client) {
+                account.credit_limit = config.credit_limit;
+            }
+            self.account_configs.insert(config.client.clone(), config);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the ledger invariants of every account known to the Ledger,
+    /// and that the debits and credits of its journal net to zero.
+    pub fn verify(&self) -> Result<(), InvariantViolation> {
+        for account in self.accounts.values() {
+            account.check_invariants(self.negative_balance_policy)?;
+        }
+
+        self.journal.check_balanced()
+    }
+
+    /// Attempts to read a CSV statement report from the provided PathBuf and
+    /// verifies the ledger invariants of every account it contains.
+    pub fn verify_snapshot(
+        path: PathBuf,
+        policy: NegativeBalancePolicy,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_path(path)?;
+
+        for statement in iter.deserialize() {
+            let statement: Statement = statement?;
+            statement.check_invariants(policy)?;
+        }
+
+        Ok(())
+    }
+
     /// Attempts to generate a CSV statement report for all accounts known to
     /// the Ledger.
     pub fn generate_account_statements(&self) -> Result<String, StatementError> {
@@ -127,9 +894,17 @@ impl Ledger {
             // Create a CSV writer from the buffer we allocated above.
             let mut wtr = csv::Writer::from_writer(&mut buf);
 
-            // Serialize each of the accounts to our output buffer.
+            // Serialize each of the accounts to our output buffer, one row
+            // per currency the account holds funds in. The client's
+            // registration is only appended when a registry is configured.
             for account in self.accounts.values() {
-                wtr.serialize(account)?;
+                let record = self.registered(&account.client);
+                for statement in account.statements() {
+                    match self.registry {
+                        Some(_) => wtr.serialize((statement, Registration::new(record)))?,
+                        None => wtr.serialize(statement)?,
+                    }
+                }
             }
 
             // Flush the buffer.
@@ -141,1034 +916,4032 @@ impl Ledger {
         Ok(String::from_utf8(buf)?)
     }
 
-    /// Process a transaction of any supported type.
-    pub fn process_transaction(&mut self, transaction: &Transaction) -> Result<(), Box<dyn Error>> {
-        match transaction.r#type {
-            TransactionType::Deposit => self.process_deposit(transaction)?,
-            TransactionType::Withdrawal => {
-                // We don't want to stop processing all of the data because a
-                // single client attempted to overdraft their account, so log
-                // an error message and keep moving if there were insufficient
-                // funds. Any other errors should be bubbled up.
-                if let Err(err) = self.process_withdrawal(transaction) {
-                    if let WithdrawalError::InsufficientFunds(wanted, had) = err {
-                        eprintln!(
-                            "insufficient funds for transaction {} wanted={} had={}",
-                            transaction.tx, wanted, had
-                        );
-                    } else {
-                        return Err(Box::new(err));
-                    }
-                }
-            }
-            TransactionType::Dispute => self.process_dispute(transaction)?,
-            TransactionType::Resolve => self.process_resolve(transaction)?,
-            TransactionType::Chargeback => self.process_chargeback(transaction)?,
-        };
+    /// Attempts to parse the CSV file located at the provided PathBuf and
+    /// streams the data into this Ledger. Rows without a source column are
+    /// namespaced by the input file they came from.
+    pub fn process_file(&mut self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        let source: Option<Arc<str>> = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(Arc::from);
 
-        Ok(())
+        // Note: the csv library handles setting up an io::BufReader so we
+        // don't need to do that here.
+        self.process_reader(std::fs::File::open(path)?, source)
     }
 
-    /// Process a deposit transaction.
+    /// Attempts to parse CSV transactions from the provided reader and
+    /// streams them into this Ledger, defaulting their source to the provided
+    /// one.
     ///
-    /// A deposit is a credit to a client's asset account, meaning it should
-    /// increase the available and total funds of the client account.
+    /// Any errors encountered while decoding CSV rows or during transaction
+    /// processing are returned immediately and the stream is closed.
     ///
-    /// A positive amount MUST be specified in the provided transaction or an
-    /// error will be returned. Locked accounts may NOT receive deposits.
-    fn process_deposit(&mut self, transaction: &Transaction) -> Result<(), DepositError> {
-        // Ensure that an amount was specified, otherwise return an error.
-        let amount = match transaction.amount {
-            None => return Err(DepositError::AmountRequired),
-            Some(amount) => amount,
+    /// Consecutive rows marked with the same batch, or every row of the input
+    /// if the Ledger applies files atomically, are applied all-or-nothing. In
+    /// a batch, rows that would otherwise be logged and skipped fail the
+    /// batch too, and a failed batch is rolled back to the state of the
+    /// Ledger before its first row and recorded in the batches output rather
+    /// than stopping all processing. A row that can't be decoded still stops
+    /// processing, after rolling back the open batch.
+    pub fn process_reader<R: io::Read>(
+        &mut self,
+        rdr: R,
+        source: Option<Arc<str>>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Create an iterator over all CSV records in the reader, which are
+        // mapped to Transaction columns by the Ledger's dialect.
+        let mut iter = self.dialect.reader_builder().from_reader(rdr);
+        let headers = self.dialect.headers(&mut iter)?;
+
+        let mut batch = if self.atomic_files {
+            Some(self.begin_batch(source.clone(), None))
+        } else {
+            None
         };
-
-        // If the specified amount was negative then return an error.
-        if amount < 0.0 {
-            return Err(DepositError::NegativeDeposit);
-        }
-        self.save_transaction(transaction)?;
-
-        match self.accounts.entry(transaction.client) {
-            Entry::Occupied(mut account) => {
-                let account = account.get_mut();
-
-                if account.locked {
-                    return Err(DepositError::AccountLocked);
+        for record in iter.byte_records() {
+            let tx = record.and_then(|record| self.dialect.deserialize(&record, &headers));
+            let mut tx: Transaction = match tx {
+                Ok(tx) => tx,
+                Err(err) => {
+                    if let Some(mut open) = batch.take() {
+                        self.rows += 1;
+                        self.fail_batch(&mut open, err.to_string());
+                        self.end_batch(open);
+                    }
+                    return Err(Box::new(err));
                 }
+            };
+            if tx.source.is_none() {
+                tx.source = source.clone();
+            }
 
-                account.deposit_funds(amount);
+            // Outside of atomic files, a batch ends at the first row which
+            // isn't marked with it.
+            if !self.atomic_files && batch.as_ref().map(|open| &open.name) != Some(&tx.batch) {
+                if let Some(open) = batch.take() {
+                    self.end_batch(open);
+                }
+                if tx.batch.is_some() {
+                    batch = Some(self.begin_batch(tx.source.clone(), tx.batch.clone()));
+                }
             }
-            Entry::Vacant(vacancy) => {
-                vacancy.insert(Account::new_account(transaction.client, amount));
+
+            match batch.as_mut() {
+                None => self.process_transaction(&tx)?,
+                // The remaining rows of a failed batch are skipped.
+                Some(open) if open.failure.is_some() => self.rows += 1,
+                Some(open) => {
+                    if let Err(err) = self.process_transaction(&tx) {
+                        self.fail_batch(open, err.to_string());
+                    }
+                }
             }
-        };
+        }
+        if let Some(open) = batch {
+            self.end_batch(open);
+        }
 
         Ok(())
     }
 
-    /// Process a withdrawal transaction.
-    ///
-    /// A withdraw is a debit to the client's asset account, meaning it should
-    /// decrease the available and total funds of the client account.
-    ///
-    /// A positive amount MUST be specified in the provided transaction or an
-    /// error will be returned. Locked accounts may NOT withdraw funds.
-    ///
-    /// If a client does not have sufficient available funds the withdrawal
-    /// will fail and the total amount of funds will not change.
-    fn process_withdrawal(&mut self, transaction: &Transaction) -> Result<(), WithdrawalError> {
-        // Ensure that an amount was specified, otherwise return an error.
-        let amount = match transaction.amount {
-            None => return Err(WithdrawalError::AmountRequired),
-            Some(amount) => amount,
+    /// Starts a batch at the next input row, opening the undo log that the
+    /// batch is rolled back with if it fails.
+    fn begin_batch(&mut self, source: Option<Arc<str>>, name: Option<Arc<str>>) -> Batch {
+        let batch = Batch {
+            source,
+            name,
+            first_row: self.rows + 1,
+            failure: None,
         };
+        self.undo = Some(UndoLog {
+            orphans: self.orphans.clone(),
+            journal: self.journal.entries().len(),
+            fee_records: self.fee_records.len(),
+            accrual_records: self.accrual_records.len(),
+            exchange_records: self.exchange_records.len(),
+            held_transactions: self.held_transactions.len(),
+            quarantine: self.quarantine.len(),
+            duplicates: self.duplicates.len(),
+            expired_orphans: self.expired_orphans.len(),
+            warnings: self.warnings.len(),
+            alerts: self.risk_rules.alerts().len(),
+            generated_transactions: self.generated_transactions,
+            last_date: self.last_date,
+            ..Default::default()
+        });
+        self.in_batch = true;
+        batch
+    }
 
-        // If the specified amount was negative then return an error.
-        if amount < 0.0 {
-            return Err(WithdrawalError::NegativeWithdrawal);
+    /// Saves the state the provided transaction may change in the open
+    /// batch's undo log, before it is applied. Besides the transaction's own
+    /// client and key, fees and spreads touch the house account, reversed
+    /// fees touch the client of the referenced transaction, and accruals
+    /// touch every account.
+    fn save_undo(&mut self, transaction: &Transaction) {
+        let house = self.house_account();
+        let undo = match self.undo.as_mut() {
+            Some(undo) => undo,
+            None => return,
+        };
+
+        let client = &transaction.client;
+        let mut clients = Vec::from([client.clone(), house]);
+        if let Some(referenced) = self.transactions.get(&transaction.key()) {
+            clients.push(referenced.client.clone());
+        }
+        if transaction.r#type == TransactionType::Accrue {
+            clients.extend(self.accounts.keys().cloned());
+        }
+        for client in clients.iter() {
+            save(&mut undo.accounts, &self.accounts, client);
+        }
+        save(&mut undo.account_configs, &self.account_configs, client);
+        save(&mut undo.client_metrics, &self.client_metrics, client);
+        if !undo.activity.contains_key(client) {
+            let activity = self.risk_rules.activity(client).cloned();
+            undo.activity.insert(client.clone(), activity);
         }
-        self.save_transaction(transaction)?;
+        save(
+            &mut undo.transaction_states,
+            &self.transaction_states,
+            &transaction.key(),
+        );
+        save(
+            &mut undo.references,
+            &self.applied_references,
+            &transaction.key(),
+        );
+        save(
+            &mut undo.recent_withdrawals,
+            &self.recent_withdrawals,
+            &(client.clone(), transaction.currency()),
+        );
+    }
 
-        match self.accounts.entry(transaction.client) {
-            Entry::Occupied(mut account) => {
-                let account = account.get_mut();
+    /// Rolls the Ledger back to the state before the batch began, using its
+    /// undo log. The input rows already read are still counted.
+    fn fail_batch(&mut self, batch: &mut Batch, reason: String) {
+        if let Some(undo) = self.undo.take() {
+            restore(&mut self.accounts, undo.accounts);
+            restore(&mut self.account_configs, undo.account_configs);
+            restore(&mut self.client_metrics, undo.client_metrics);
+            restore(&mut self.transaction_states, undo.transaction_states);
+            restore(&mut self.recent_withdrawals, undo.recent_withdrawals);
+            self.risk_rules.restore(undo.alerts, undo.activity);
+            for key in undo.transactions.iter() {
+                self.transactions.remove(key);
+            }
+            restore(&mut self.applied_references, undo.references);
+            self.orphans = undo.orphans;
+            self.journal.truncate(undo.journal);
+            self.fee_records.truncate(undo.fee_records);
+            self.accrual_records.truncate(undo.accrual_records);
+            self.exchange_records.truncate(undo.exchange_records);
+            self.held_transactions.truncate(undo.held_transactions);
+            self.quarantine.truncate(undo.quarantine);
+            self.duplicates.truncate(undo.duplicates);
+            self.expired_orphans.truncate(undo.expired_orphans);
+            self.warnings.truncate(undo.warnings);
+            self.generated_transactions = undo.generated_transactions;
+            self.last_date = undo.last_date;
+        }
+        batch.failure = Some(reason);
+    }
 
-                if account.locked {
-                    return Err(WithdrawalError::AccountLocked);
-                }
+    /// Ends the batch, recording whether it was committed or rolled back in
+    /// the batches output.
+    fn end_batch(&mut self, batch: Batch) {
+        self.in_batch = false;
+        self.undo = None;
+        let status = match &batch.failure {
+            Some(reason) => {
+                eprintln!(
+                    "rolled back batch {} of {}: {}",
+                    batch.name.as_deref().unwrap_or("*"),
+                    batch.source.as_deref().unwrap_or("input"),
+                    reason
+                );
+                "rolled_back"
+            }
+            None => "committed",
+        };
+        self.batches.push(BatchRecord {
+            source: batch.source,
+            batch: batch.name,
+            first_row: batch.first_row,
+            last_row: self.rows,
+            status,
+            reason: batch.failure,
+        });
+    }
 
-                if account.available - amount < 0.0 {
-                    return Err(WithdrawalError::InsufficientFunds(
-                        amount,
-                        account.available,
-                    ));
-                }
+    /// Attempts to generate a CSV report of the batches applied by the
+    /// Ledger, along with the reason each rolled back batch failed.
+    pub fn generate_batch_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.batches.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate an itemized CSV report of all fees charged, and
+    /// reversed, by the Ledger.
+    pub fn generate_fee_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.fee_records.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
+        }
 
-                account.withdraw_funds(amount);
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of all interest posted by accrual
+    /// runs.
+    pub fn generate_accrual_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.accrual_records.iter() {
+                wtr.serialize(record)?;
             }
-            Entry::Vacant(_) => return Err(WithdrawalError::NoSuchAccount(transaction.client)),
-        };
+            let _ = wtr.flush();
+        }
 
-        Ok(())
+        Ok(String::from_utf8(buf)?)
     }
 
-    /// Process a dispute transaction.
+    /// Process a transaction of any supported type.
     ///
-    /// A dispute represents a client's claim that a transaction was erroneous
-    /// and should be reversed. The transaction shouldn't be reversed yet but
-    /// the associated funds should be held. This means that the clients
-    /// available funds should decrease by the amount disputed, their held
-    /// funds should increase by the amount disputed, while their total funds
-    /// should remain the same.
+    /// The Ledger's risk rules are evaluated before the transaction is
+    /// applied. Transactions the rules hold are set aside for review and
+    /// transactions they reject are dropped, in both cases with an alert.
     ///
-    /// Note: a dispute does not state the amount disputed. Instead a dispute
-    /// references the transaction that is disputed by ID. If the tx specified
-    /// by the dispute doesn't exist it will be ignored and the assumption will
-    /// be that this is an error on our partners side.
-    fn process_dispute(&mut self, transaction: &Transaction) -> Result<(), DisputeError> {
-        if let Entry::Occupied(mut tx) = self.transactions.entry(transaction.tx) {
-            let tx = tx.get_mut();
-            let amount = match tx.amount {
-                Some(amount) => amount,
-                None => return Err(DisputeError::AmountRequired),
-            };
+    /// Re-ingesting a transaction that was already applied, with the same
+    /// content, is a no-op which is recorded in the duplicates output. A
+    /// transaction which reuses the tx id of an applied transaction with
+    /// different content is an error.
+    ///
+    /// Within the reorder window, a dispute, resolve or chargeback which
+    /// references a transaction that hasn't arrived yet is parked and
+    /// retried once the transaction is applied.
+    pub fn process_transaction(&mut self, transaction: &Transaction) -> Result<(), Box<dyn Error>> {
+        self.rows += 1;
+        if transaction.date.is_some() {
+            self.last_date = transaction.date;
+        }
+        self.expire_orphans_before(self.rows.saturating_sub(self.reorder_window));
 
-            let mut account = self.get_account_entry(transaction.client)?;
-            let account = account.get_mut();
+        // Only the Ledger credits the house account, so input rows for it
+        // are rejected rather than letting a client collect its fees. The
+        // client of an accrue row is ignored.
+        if transaction.client == self.house_account()
+            && transaction.r#type != TransactionType::Accrue
+        {
+            let err = HouseAccountError::new(transaction.client.clone());
+            if self.in_batch {
+                return Err(Box::new(err));
+            }
+            eprintln!("rejected transaction {}: {}", transaction.tx, err);
+            return Ok(());
+        }
 
-            account.available -= amount;
-            account.held += amount;
-            self.disputed_transactions.insert(transaction.tx);
+        if self.is_already_applied(transaction) {
+            self.duplicates.push(DuplicateRecord {
+                row: self.rows,
+                source: transaction.source.clone(),
+                tx: transaction.tx,
+                r#type: transaction.r#type.clone(),
+                client: transaction.client.clone(),
+                status: "already_applied",
+            });
+            return Ok(());
         }
 
-        Ok(())
+        if self.is_orphan(transaction) {
+            self.orphans.push_back((self.rows, transaction.clone()));
+            return Ok(());
+        }
+
+        self.evaluate_transaction(transaction)
     }
 
-    /// Process a resolve transaction.
-    ///
-    /// A resolve represents a resolution to a dispute, releasing the
-    /// associated held funds. Funds that were previously disputed are no
-    /// longer disputed. This means that the clients held funds should decrease
-    /// by the amount no longer disputed, their available funds should increase
-    /// by the amount no longer disputed, and their total funds should remain
-    /// the same.
-    ///
-    /// Note: Like disputes, resolves do not specify an amount. Instead they
-    /// refer to a transaction that was under dispute by ID. If the tx
-    /// specified doesn't exist, or the tx isn't under dispute, the resolve is
-    /// ignored and the assumption is made that this is an error on our
-    /// partner's side.
-    fn process_resolve(&mut self, transaction: &Transaction) -> Result<(), ResolveError> {
-        if let Entry::Occupied(tx) = self.transactions.entry(transaction.tx) {
-            // If this transaction aims to resolve an undisputed transaction
-            // then we simply skip over it.
-            if !self.disputed_transactions.contains(&transaction.tx) {
+    /// Evaluates the Ledger's risk rules against the transaction and applies
+    /// it unless the rules hold or reject it.
+    fn evaluate_transaction(&mut self, transaction: &Transaction) -> Result<(), Box<dyn Error>> {
+        match self.risk_rules.evaluate(transaction, self.rows) {
+            RiskAction::Hold => {
+                self.held_transactions.push(transaction.clone());
                 return Ok(());
             }
+            RiskAction::Reject => return Ok(()),
+            RiskAction::Allow | RiskAction::Flag => {}
+        }
 
-            let tx = tx.get();
-            let amount = match tx.amount {
-                Some(a) => a,
-                None => return Err(ResolveError::AmountRequired),
-            };
+        self.apply_transaction(transaction)?;
+        self.retry_orphans(&transaction.key())
+    }
 
-            let mut account = self.get_account_entry(transaction.client)?;
-            let account = account.get_mut();
+    /// Returns whether the transaction is a dispute, resolve or chargeback
+    /// referencing a transaction that hasn't arrived yet, and should be
+    /// parked in the reorder window.
+    fn is_orphan(&self, transaction: &Transaction) -> bool {
+        self.reorder_window > 0
+            && transaction.is_reference()
+            && !self.transactions.contains_key(&transaction.key())
+    }
+
+    /// Applies the parked orphans referencing the provided key, in the order
+    /// they were read, once the transaction they reference has arrived.
+    fn retry_orphans(&mut self, key: &TransactionKey) -> Result<(), Box<dyn Error>> {
+        if self.orphans.is_empty() || !self.transactions.contains_key(key) {
+            return Ok(());
+        }
 
-            account.available += amount;
-            account.held -= amount;
-            self.disputed_transactions.remove(&transaction.tx);
+        let (ready, parked): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.orphans)
+            .into_iter()
+            .partition(|(_, orphan)| orphan.key() == *key);
+        self.orphans = parked;
+        for (_, orphan) in ready {
+            self.evaluate_transaction(&orphan)?;
         }
 
         Ok(())
     }
 
-    /// Process a chargeback transaction.
-    ///
-    /// A chargeback is the final state of a dispute and represents the client
-    /// reversing a transaction. Funds that were held have now been withdrawn.
-    /// This means that the clients held funds and total funds should decrease
-    /// by the amount previously disputed. If a chargeback occurs the client's
-    /// account should be immediately frozen.
-    ///
-    /// Note: Like a dispute and a resolve a chargeback refers to the
-    /// transaction by ID (tx) and does not specify an amount. Like a resolve,
-    /// if the tx specified doesn't exist, or the tx isn't under dispute, the
-    /// chargeback will be ignored and the assumption will be made that this is
-    /// an error on our partner's side.
-    fn process_chargeback(&mut self, transaction: &Transaction) -> Result<(), ChargebackError> {
-        if let Entry::Occupied(tx) = self.transactions.entry(transaction.tx) {
-            // If this transaction aims to resolve an undisputed transaction
-            // then we simply skip over it.
-            if !self.disputed_transactions.contains(&transaction.tx) {
-                return Ok(());
+    /// Expires the orphans parked before the provided row, recording them in
+    /// the orphans output.
+    fn expire_orphans_before(&mut self, row: u64) {
+        while self
+            .orphans
+            .front()
+            .is_some_and(|(parked, _)| *parked < row)
+        {
+            if let Some((parked, orphan)) = self.orphans.pop_front() {
+                self.expired_orphans.push(OrphanRecord {
+                    row: parked,
+                    source: orphan.source,
+                    tx: orphan.tx,
+                    r#type: orphan.r#type,
+                    client: orphan.client,
+                    expired_row: self.rows,
+                });
             }
+        }
+    }
 
-            let tx = tx.get();
-            let amount = match tx.amount {
-                Some(a) => a,
-                None => return Err(ChargebackError::AmountRequired),
-            };
+    /// Expires every orphan still parked in the reorder window, e.g. once
+    /// all input has been processed.
+    pub fn expire_orphans(&mut self) {
+        self.expire_orphans_before(u64::MAX);
+    }
 
-            let mut account = self.get_account_entry(transaction.client)?;
-            let account = account.get_mut();
-            account.held -= amount;
-            account.total = account.available - account.held;
-            account.lock();
+    /// Attempts to generate a CSV report of the disputes, resolves and
+    /// chargebacks which expired before the transaction they reference
+    /// arrived.
+    pub fn generate_orphan_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.expired_orphans.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
         }
 
-        Ok(())
+        Ok(String::from_utf8(buf)?)
     }
 
-    /// Fetch attempt to fetch an OccupiedEntry which contains an existing
-    /// Account.
-    fn get_account_entry(&mut self, id: u16) -> Result<OccupiedEntry<u16, Account>, AccountError> {
-        match self.accounts.entry(id) {
-            Entry::Occupied(account) => {
-                if account.get().locked {
-                    return Err(AccountError::AccountLocked(id));
+    /// Releases a transaction that was held by the risk rules, applying it
+    /// without evaluating the rules again. Returns whether a held transaction
+    /// with the provided key was found.
+    pub fn release_held(&mut self, key: &TransactionKey) -> Result<bool, Box<dyn Error>> {
+        let index = match self
+            .held_transactions
+            .iter()
+            .position(|held| held.key() == *key)
+        {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+
+        let transaction = self.held_transactions.remove(index);
+        self.rows += 1;
+        self.apply_transaction(&transaction)?;
+        self.retry_orphans(&transaction.key())?;
+
+        Ok(true)
+    }
+
+    /// Applies a transaction of any supported type to the Ledger and records
+    /// it in the activity the risk rules are evaluated against.
+    ///
+    /// Inside a batch, transactions that would otherwise be logged and
+    /// skipped, or quarantined, are returned as errors so that the batch is
+    /// rolled back.
+    fn apply_transaction(&mut self, transaction: &Transaction) -> Result<(), Box<dyn Error>> {
+        self.save_undo(transaction);
+        match transaction.r#type {
+            TransactionType::Deposit => {
+                // Deposits for blocklisted clients are quarantined rather
+                // than stopping all processing, and deposits for clients
+                // missing from the registry in strict mode, or too small to
+                // cover their fee, are logged and skipped.
+                match self.process_deposit(transaction) {
+                    Err(err) if self.in_batch => return Err(Box::new(err)),
+                    Err(DepositError::ScreeningHit(reason)) => {
+                        self.quarantine_transaction(transaction, reason)
+                    }
+                    Err(err @ DepositError::NoSuchAccount(_)) => {
+                        eprintln!("rejected transaction {}: {}", transaction.tx, err)
+                    }
+                    Err(DepositError::FeeExceedsAmount(fee, amount)) => eprintln!(
+                        "fee exceeds the deposited amount for transaction {} fee={} amount={}",
+                        transaction.tx, fee, amount
+                    ),
+                    Err(err) => return Err(Box::new(err)),
+                    Ok(()) => {}
+                }
+            }
+            TransactionType::Withdrawal => {
+                // We don't want to stop processing all of the data because a
+                // single client attempted to overdraft their account, so log
+                // an error message and keep moving if there were insufficient
+                // funds. Any other errors should be bubbled up.
+                // The same goes for withdrawals that exceed the client's
+                // withdrawal limits.
+                match self.process_withdrawal(transaction) {
+                    Err(err) if self.in_batch => return Err(Box::new(err)),
+                    Err(WithdrawalError::InsufficientFunds(wanted, had, limit)) => eprintln!(
+                        "insufficient funds for transaction {} wanted={} had={} credit_limit={}",
+                        transaction.tx, wanted, had, limit
+                    ),
+                    Err(WithdrawalError::LimitExceeded(kind, limit, attempted)) => eprintln!(
+                        "withdrawal limit exceeded for transaction {} limit={} max={} attempted={}",
+                        transaction.tx, kind, limit, attempted
+                    ),
+                    Err(err) => return Err(Box::new(err)),
+                    Ok(()) => {}
+                }
+            }
+            TransactionType::Dispute => {
+                // Like withdrawals, a dispute of funds the client no longer
+                // has is rejected without stopping all processing, and is
+                // reported back to the partner.
+                match self.process_dispute(transaction) {
+                    Err(err) if self.in_batch => return Err(Box::new(err)),
+                    Err(DisputeError::InsufficientFunds(wanted, had)) => {
+                        eprintln!(
+                            "insufficient funds to dispute transaction {} wanted={} had={}",
+                            transaction.tx, wanted, had
+                        );
+                        self.warn(transaction, PartnerWarning::InsufficientFunds);
+                    }
+                    Err(err) => return Err(Box::new(err)),
+                    Ok(()) => {}
+                }
+            }
+            TransactionType::Resolve => self.process_resolve(transaction)?,
+            TransactionType::Chargeback => self.process_chargeback(transaction)?,
+            TransactionType::CreditLimit => self.process_credit_limit(transaction)?,
+            TransactionType::Accrue => self.process_accrue(transaction)?,
+            TransactionType::Refund => {
+                // Like withdrawals, a refund that the client can no longer
+                // cover shouldn't stop us from processing the rest of the
+                // data, so log an error message and keep moving. Any other
+                // refund error is a malformed partner row, and like malformed
+                // disputes it is logged and skipped.
+                match self.process_refund(transaction) {
+                    Err(err) if self.in_batch => return Err(Box::new(err)),
+                    Err(RefundError::InsufficientFunds(wanted, had)) => eprintln!(
+                        "insufficient funds to refund transaction {} wanted={} had={}",
+                        transaction.tx, wanted, had
+                    ),
+                    Err(err) => eprintln!("rejected refund {}: {}", transaction.tx, err),
+                    Ok(()) => {}
+                }
+            }
+            TransactionType::Exchange => {
+                // Like withdrawals, an exchange the client can't cover is
+                // logged and skipped rather than stopping all processing, and
+                // like deposits, exchanges for blocklisted clients are
+                // quarantined.
+                match self.process_exchange(transaction) {
+                    Err(err) if self.in_batch => return Err(Box::new(err)),
+                    Err(ExchangeError::InsufficientFunds(wanted, had)) => eprintln!(
+                        "insufficient funds to exchange transaction {} wanted={} had={}",
+                        transaction.tx, wanted, had
+                    ),
+                    Err(ExchangeError::ScreeningHit(reason)) => {
+                        self.quarantine_transaction(transaction, reason)
+                    }
+                    Err(err) => return Err(Box::new(err)),
+                    Ok(()) => {}
                 }
+            }
+        };
 
-                Ok(account)
+        // Verify that the transaction left the client's account in a
+        // consistent state when running in debug or verification mode.
+        if self.verify || cfg!(debug_assertions) {
+            if let Some(account) = self.accounts.get(&transaction.client) {
+                account.check_invariants(self.negative_balance_policy)?;
             }
-            Entry::Vacant(_) => Err(AccountError::NoSuchAccount(id)),
         }
+        self.risk_rules.record(transaction, self.rows);
+
+        Ok(())
+    }
+
+    /// Records a dispute, resolve or chargeback that was ignored in the
+    /// partner warnings output.
+    fn warn(&mut self, transaction: &Transaction, warning: PartnerWarning) {
+        self.warnings.push(WarningRecord {
+            row: self.rows,
+            source: transaction.source.clone(),
+            tx: transaction.tx,
+            r#type: transaction.r#type.clone(),
+            client: transaction.client.clone(),
+            warning,
+        });
+    }
+
+    /// Returns the number of ignored rows recorded under each partner
+    /// warning.
+    pub fn warning_counts(&self) -> BTreeMap<PartnerWarning, usize> {
+        let mut counts: BTreeMap<_, _> = PartnerWarning::ALL.iter().map(|w| (*w, 0)).collect();
+        for record in self.warnings.iter() {
+            *counts.entry(record.warning).or_default() += 1;
+        }
+        counts
     }
 
-    fn save_transaction(
-        &mut self,
-        transaction: &Transaction,
-    ) -> Result<(), DuplicateTransactionError> {
-        match self.transactions.entry(transaction.tx) {
-            Entry::Occupied(_) => return Err(DuplicateTransactionError::new(transaction.tx)),
-            Entry::Vacant(entry) => {
-                entry.insert(transaction.clone());
+    /// Attempts to generate a CSV report of the disputes, resolves and
+    /// chargebacks that were ignored, to be sent back to partners.
+    pub fn generate_warning_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.warnings.iter() {
+                wtr.serialize(record)?;
             }
-        };
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to apply the statement lines of an MT940 statement to the
+    /// account its bank account is mapped to, checking the account's balance
+    /// in the statement's currency against the opening balance before and the
+    /// closing balance after. Mismatches are recorded in the reconciliation
+    /// output rather than failing the statement.
+    ///
+    /// The opening balance is compared to the balance from before any of the
+    /// statement's lines were applied, so that importing a statement again
+    /// doesn't report a mismatch.
+    pub fn process_mt940_statement(
+        &mut self,
+        statement: &Mt940Statement,
+        accounts: &BankAccounts,
+    ) -> Result<(), Box<dyn Error>> {
+        let transactions = statement.transactions(accounts)?;
+        let client = statement.client(accounts)?;
+
+        if let Some(opening) = statement.opening {
+            let applied: f64 = transactions
+                .iter()
+                .filter(|transaction| self.is_already_applied(transaction))
+                .map(|transaction| match transaction.r#type {
+                    TransactionType::Withdrawal => -transaction.amount.unwrap_or_default(),
+                    _ => transaction.amount.unwrap_or_default(),
+                })
+                .sum();
+            self.reconcile(statement, client, "opening", opening, applied);
+        }
+        for transaction in transactions.iter() {
+            self.process_transaction(transaction)?;
+        }
+        if let Some(closing) = statement.closing {
+            self.reconcile(statement, client, "closing", closing, 0.0);
+        }
 
         Ok(())
     }
-}
 
-impl TryFrom<PathBuf> for Ledger {
-    type Error = Box<dyn Error>;
+    /// Records a mismatch between a statement balance and the total funds of
+    /// the client's account in the statement's currency, less the provided
+    /// amount the statement has already applied to them.
+    fn reconcile(
+        &mut self,
+        statement: &Mt940Statement,
+        client: &ClientId,
+        balance: &'static str,
+        expected: Mt940Balance,
+        applied: f64,
+    ) {
+        let currency = expected.currency;
+        let actual = self
+            .accounts
+            .get(client)
+            .and_then(|account| account.balance(currency))
+            .map_or(0.0, |balance| currency.round(balance.total - applied));
+        let expected = currency.round(expected.signed());
+        if currency.round(actual) == expected {
+            return;
+        }
 
-    /// Attempts to parse the CSV file located at the provided PathBuf and
-    /// streams the data into a newly allocated Ledger.
-    ///
-    /// Any errors encountered while decoding CSV rows or during transaction
-    /// processing are returned immediately and the stream is closed.
-    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        // Allocate a new mutable ledger that we can populate from decoded CSV
-        // transactions.
-        let mut ledger = Ledger::default();
+        eprintln!(
+            "statement {} of account {}: {} balance is {} {} but client {} holds {}",
+            statement.reference, statement.account, balance, expected, currency, client, actual
+        );
+        self.reconciliations.push(ReconciliationRecord {
+            statement: statement.reference.clone(),
+            account: statement.account.clone(),
+            client: client.clone(),
+            currency,
+            balance,
+            expected,
+            actual,
+        });
+    }
 
-        // Create an iterator over all CSV records in the specified CSV file
-        // path.
-        //
-        // Note: the csv library handles setting up an io::BufReader so we
-        // don't need to do that here.
-        let mut iter = csv::ReaderBuilder::new()
-            .flexible(true)
-            .has_headers(true)
-            .trim(Trim::All)
-            .from_path(path)?;
+    /// Attempts to generate a CSV report of the statement balances which
+    /// didn't match the balances of the accounts they were imported into.
+    pub fn generate_reconciliation_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.reconciliations.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Records a transaction that was not applied because of a screening hit
+    /// in the quarantine output.
+    fn quarantine_transaction(&mut self, transaction: &Transaction, reason: String) {
+        self.quarantine.push(QuarantineRecord {
+            row: self.rows,
+            r#type: transaction.r#type.clone(),
+            client: transaction.client.clone(),
+            tx: transaction.tx,
+            amount: transaction.amount,
+            currency: transaction.currency(),
+            reason,
+        });
+    }
 
-        for tx in iter.deserialize() {
-            let tx: Transaction = tx?;
-            ledger.process_transaction(&tx)?;
+    /// Attempts to generate a CSV report of the input rows that were skipped
+    /// because they had already been applied.
+    pub fn generate_duplicate_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.duplicates.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
         }
 
-        Ok(ledger)
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of the transactions quarantined by
+    /// screening, along with the reason for each screening hit.
+    pub fn generate_quarantine_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.quarantine.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of the dispute and chargeback
+    /// ratios of every client that has made a deposit, disputed or charged
+    /// back a transaction.
+    pub fn generate_monitoring_report(&self) -> Result<String, StatementError> {
+        let mut clients: Vec<_> = self.client_metrics.keys().collect();
+        clients.sort_unstable();
+
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for client in clients {
+                let metrics = self.client_metrics[client];
+                let account = self.accounts.get(client);
+                wtr.serialize(MonitoringRecord {
+                    client: client.clone(),
+                    deposits: metrics.deposits,
+                    disputes: metrics.disputes,
+                    chargebacks: metrics.chargebacks,
+                    dispute_ratio: metrics.dispute_ratio(),
+                    chargeback_ratio: metrics.chargeback_ratio(),
+                    review: account.is_some_and(|account| account.review),
+                    locked: account.is_some_and(|account| account.locked),
+                })?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of the alerts raised by the risk
+    /// rules.
+    pub fn generate_alert_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for alert in self.risk_rules.alerts() {
+                wtr.serialize(alert)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of the rates used for, and spread
+    /// revenue earned on, all exchanges processed by the Ledger.
+    pub fn generate_exchange_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for record in self.exchange_records.iter() {
+                wtr.serialize(record)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV report of every entry posted to the
+    /// Ledger's journal.
+    pub fn generate_journal_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for entry in self.journal.entries() {
+                wtr.serialize(entry)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Attempts to generate a CSV trial balance of the Ledger's journal. The
+    /// debits and credits of each currency must net to zero.
+    pub fn generate_trial_balance(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for row in self.journal.trial_balance() {
+                wtr.serialize(row)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Process a deposit transaction.
+    ///
+    /// A deposit is a credit to a client's asset account, meaning it should
+    /// increase the available and total funds of the client account in the
+    /// currency of the deposit.
+    ///
+    /// A positive amount MUST be specified in the provided transaction or an
+    /// error will be returned. Locked accounts may NOT receive deposits.
+    ///
+    /// Any fee charged for the deposit is deducted from the deposited amount
+    /// and credited to the house account.
+    ///
+    /// Clients on the Ledger's blocklist are screened before their account is
+    /// created or credited, and their deposits are refused. When the Ledger's
+    /// client registry is strict, deposits from unregistered clients are
+    /// refused rather than opening an account.
+    fn process_deposit(&mut self, transaction: &Transaction) -> Result<(), DepositError> {
+        // Ensure that an amount was specified, otherwise return an error.
+        let amount = match transaction.amount {
+            None => return Err(DepositError::AmountRequired),
+            Some(amount) => amount,
+        };
+
+        // If the specified amount was negative then return an error.
+        if amount < 0.0 {
+            return Err(DepositError::NegativeDeposit);
+        }
+
+        let currency = transaction.currency();
+        let fee = self.fee_for(transaction, amount);
+        if fee > amount {
+            return Err(DepositError::FeeExceedsAmount(fee, amount));
+        }
+        if let Some(reason) = self.screen(&transaction.client) {
+            return Err(DepositError::ScreeningHit(reason));
+        }
+
+        // A client's first deposit opens their account.
+        let opened = if self.accounts.contains_key(&transaction.client) {
+            None
+        } else {
+            Some(self.open_account(&transaction.client)?)
+        };
+        self.check_duplicate(transaction)?;
+
+        let account = match opened {
+            Some(account) => self
+                .accounts
+                .entry(transaction.client.clone())
+                .or_insert(account),
+            None => self.accounts.get_mut(&transaction.client).unwrap(),
+        };
+        if account.locked {
+            return Err(DepositError::AccountLocked);
+        }
+        self.post(
+            transaction.tx,
+            currency,
+            LedgerAccount::Settlement,
+            LedgerAccount::ClientLiability(transaction.client.clone()),
+            amount,
+        );
+        self.charge_fee(transaction, amount, fee);
+        self.save_transaction(transaction);
+        self.client_metrics
+            .entry(transaction.client.clone())
+            .or_default()
+            .deposits += 1;
+
+        Ok(())
+    }
+
+    /// Process a withdrawal transaction.
+    ///
+    /// A withdraw is a debit to the client's asset account, meaning it should
+    /// decrease the available and total funds of the client account in the
+    /// currency of the withdrawal.
+    ///
+    /// A positive amount MUST be specified in the provided transaction or an
+    /// error will be returned. Locked accounts may NOT withdraw funds.
+    ///
+    /// If a client does not have sufficient available funds, including their
+    /// approved credit line, to cover the withdrawal and any fee charged for
+    /// it, the withdrawal will fail and the total amount of funds will not
+    /// change.
+    fn process_withdrawal(&mut self, transaction: &Transaction) -> Result<(), WithdrawalError> {
+        // Ensure that an amount was specified, otherwise return an error.
+        let amount = match transaction.amount {
+            None => return Err(WithdrawalError::AmountRequired),
+            Some(amount) => amount,
+        };
+
+        // If the specified amount was negative then return an error.
+        if amount < 0.0 {
+            return Err(WithdrawalError::NegativeWithdrawal);
+        }
+        self.check_duplicate(transaction)?;
+
+        let currency = transaction.currency();
+        let fee = self.fee_for(transaction, amount);
+
+        match self.accounts.get_mut(&transaction.client) {
+            Some(account) => {
+                if account.locked {
+                    return Err(WithdrawalError::AccountLocked);
+                }
+
+                let credit_limit = account.credit_limit_for(currency);
+                let available = account.balance_mut(currency).available;
+                if available + credit_limit - amount - fee < 0.0 {
+                    return Err(WithdrawalError::InsufficientFunds(
+                        currency.round(amount + fee),
+                        available,
+                        credit_limit,
+                    ));
+                }
+            }
+            None => return Err(WithdrawalError::NoSuchAccount(transaction.client.clone())),
+        };
+        self.check_withdrawal_limits(transaction, amount)?;
+        self.post(
+            transaction.tx,
+            currency,
+            LedgerAccount::ClientLiability(transaction.client.clone()),
+            LedgerAccount::Settlement,
+            amount,
+        );
+        self.charge_fee(transaction, amount, fee);
+        self.save_transaction(transaction);
+
+        Ok(())
+    }
+
+    /// Checks a withdrawal of the provided amount against the withdrawal
+    /// limits of the client's tier, and counts it towards the client's rolling
+    /// window if it is within them. Limits are configured, and apply, per
+    /// currency.
+    fn check_withdrawal_limits(
+        &mut self,
+        transaction: &Transaction,
+        amount: f64,
+    ) -> Result<(), WithdrawalError> {
+        let tier = self
+            .account_configs
+            .get(&transaction.client)
+            .and_then(|config| config.tier.as_deref());
+        let currency = transaction.currency();
+        let limit = match self.withdrawal_limits.for_tier(tier, currency) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let day = transaction.date.map(|date| date.days_since_epoch());
+        let recent = self
+            .recent_withdrawals
+            .entry((transaction.client.clone(), currency))
+            .or_default();
+        limit
+            .check(recent, self.rows, day, amount)
+            .map_err(|(kind, limit, attempted)| {
+                WithdrawalError::LimitExceeded(kind, limit, currency.round(attempted))
+            })?;
+
+        recent.push_back(RecentWithdrawal {
+            row: self.rows,
+            day,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Process a dispute transaction.
+    ///
+    /// A dispute represents a client's claim that a transaction was erroneous
+    /// and should be reversed. The transaction shouldn't be reversed yet but
+    /// the associated funds should be held. This means that the clients
+    /// available funds should decrease by the amount disputed, their held
+    /// funds should increase by the amount disputed, while their total funds
+    /// should remain the same. Funds are always held in the currency of the
+    /// disputed transaction.
+    ///
+    /// A dispute may specify an amount, in which case only that part of the
+    /// referenced transaction is disputed. The amount may not exceed the
+    /// original amount minus any parts that are already disputed, have been
+    /// charged back, or have been refunded. If no amount is specified the
+    /// entire remaining amount of the referenced transaction is disputed.
+    ///
+    /// If the client no longer has enough available funds to cover the
+    /// disputed amount, the Ledger's NegativeBalancePolicy determines whether
+    /// the dispute is rejected, drives the available funds negative, or only
+    /// holds what is still available.
+    ///
+    /// Note: a dispute references the transaction that is disputed by ID. If
+    /// the tx specified by the dispute doesn't exist, has nothing left to
+    /// dispute, or has less left than the disputed amount, or the amount is
+    /// negative, it will be ignored and the assumption will be that this is
+    /// an error on our partners side. Ignored disputes are recorded in the
+    /// partner warnings output.
+    fn process_dispute(&mut self, transaction: &Transaction) -> Result<(), DisputeError> {
+        let key = transaction.key();
+        // Accrues are control transactions, and can't be disputed.
+        let referenced = self
+            .transactions
+            .get(&key)
+            .filter(|tx| tx.r#type != TransactionType::Accrue);
+        if let Some(tx) = referenced {
+            let original = match tx.amount {
+                Some(amount) => amount,
+                None => return Err(DisputeError::AmountRequired),
+            };
+
+            let currency = tx.currency();
+            let disputable = match self.transaction_states.get(&key) {
+                Some(state) => state.remaining(original, currency),
+                None => currency.round(original),
+            };
+            if disputable <= 0.0 {
+                self.warn(transaction, PartnerWarning::AlreadyDisputed);
+                return Ok(());
+            }
+            let amount = match transaction.amount {
+                Some(amount) if amount < 0.0 => {
+                    self.warn(transaction, PartnerWarning::NegativeAmount);
+                    return Ok(());
+                }
+                Some(amount) if amount > disputable => {
+                    self.warn(transaction, PartnerWarning::ExceedsDisputable);
+                    return Ok(());
+                }
+                Some(amount) => currency.round(amount),
+                None => disputable,
+            };
+
+            let policy = self.negative_balance_policy;
+            let mut account = self.get_account_entry(&transaction.client)?;
+            let available = account.get_mut().balance_mut(currency).available;
+
+            let amount = match policy {
+                NegativeBalancePolicy::Reject if amount > available => {
+                    return Err(DisputeError::InsufficientFunds(amount, available))
+                }
+                NegativeBalancePolicy::HoldAvailable => amount.min(available.max(0.0)),
+                _ => amount,
+            };
+
+            self.post(
+                transaction.tx,
+                currency,
+                LedgerAccount::ClientLiability(transaction.client.clone()),
+                LedgerAccount::Suspense(transaction.client.clone()),
+                amount,
+            );
+
+            let state = self.transaction_states.entry(key).or_default();
+            state.disputed = currency.round(state.disputed + amount);
+            self.client_metrics
+                .entry(transaction.client.clone())
+                .or_default()
+                .disputes += 1;
+            self.save_reference(transaction);
+        } else {
+            self.warn(transaction, PartnerWarning::UnknownTx);
+        }
+
+        Ok(())
+    }
+
+    /// Process a resolve transaction.
+    ///
+    /// A resolve represents a resolution to a dispute, releasing the
+    /// associated held funds. Funds that were previously disputed are no
+    /// longer disputed. This means that the clients held funds should decrease
+    /// by the amount no longer disputed, their available funds should increase
+    /// by the amount no longer disputed, and their total funds should remain
+    /// the same.
+    ///
+    /// A resolve may specify an amount to release only part of the disputed
+    /// funds, otherwise all of the outstanding disputed amount is released.
+    ///
+    /// Note: Like disputes, resolves refer to a transaction that was under
+    /// dispute by ID. If the tx specified doesn't exist, the tx isn't under
+    /// dispute, or the amount is negative or exceeds the disputed amount, the
+    /// resolve is ignored and the assumption is made that this is an error on
+    /// our partner's side. Ignored resolves are recorded in the partner
+    /// warnings output.
+    fn process_resolve(&mut self, transaction: &Transaction) -> Result<(), ResolveError> {
+        let key = transaction.key();
+        if let Some(tx) = self.transactions.get(&key) {
+            // If this transaction aims to resolve an undisputed transaction
+            // then we simply skip over it.
+            let disputed = match self.transaction_states.get(&key) {
+                Some(state) if state.is_disputed() => state.disputed,
+                _ => {
+                    self.warn(transaction, PartnerWarning::NotDisputed);
+                    return Ok(());
+                }
+            };
+
+            if tx.amount.is_none() {
+                return Err(ResolveError::AmountRequired);
+            }
+
+            let currency = tx.currency();
+            let amount = match transaction.amount {
+                Some(amount) if amount < 0.0 => {
+                    self.warn(transaction, PartnerWarning::NegativeAmount);
+                    return Ok(());
+                }
+                Some(amount) if amount > disputed => {
+                    self.warn(transaction, PartnerWarning::ExceedsDisputed);
+                    return Ok(());
+                }
+                Some(amount) => currency.round(amount),
+                None => disputed,
+            };
+
+            self.get_account_entry(&transaction.client)?;
+            self.post(
+                transaction.tx,
+                currency,
+                LedgerAccount::Suspense(transaction.client.clone()),
+                LedgerAccount::ClientLiability(transaction.client.clone()),
+                amount,
+            );
+
+            if let Some(state) = self.transaction_states.get_mut(&key) {
+                state.disputed = currency.round(state.disputed - amount);
+            }
+            self.save_reference(transaction);
+        } else {
+            self.warn(transaction, PartnerWarning::UnknownTx);
+        }
+
+        Ok(())
+    }
+
+    /// Process a chargeback transaction.
+    ///
+    /// A chargeback is the final state of a dispute and represents the client
+    /// reversing a transaction. Funds that were held have now been withdrawn.
+    /// This means that the clients held funds and total funds should decrease
+    /// by the amount previously disputed. If a chargeback occurs the client's
+    /// account is frozen, or placed under review if the Ledger's
+    /// ChargebackPolicy tolerates it.
+    ///
+    /// A chargeback may specify an amount to charge back only part of the
+    /// disputed funds, otherwise all of the outstanding disputed amount is
+    /// charged back. The fee charged for the original transaction is reversed
+    /// in proportion to the amount charged back.
+    ///
+    /// Note: Like a dispute and a resolve a chargeback refers to the
+    /// transaction by ID (tx). Like a resolve, if the tx specified doesn't
+    /// exist, the tx isn't under dispute, or the amount is negative or
+    /// exceeds the disputed amount, the chargeback will be ignored and the
+    /// assumption will be made that this is an error on our partner's side.
+    /// Ignored chargebacks are recorded in the partner warnings output.
+    fn process_chargeback(&mut self, transaction: &Transaction) -> Result<(), ChargebackError> {
+        let key = transaction.key();
+        if let Some(tx) = self.transactions.get(&key) {
+            let original = tx.clone();
+
+            // If this transaction aims to charge back an undisputed
+            // transaction then we simply skip over it.
+            let disputed = match self.transaction_states.get(&key) {
+                Some(state) if state.is_disputed() => state.disputed,
+                _ => {
+                    self.warn(transaction, PartnerWarning::NotDisputed);
+                    return Ok(());
+                }
+            };
+
+            if tx.amount.is_none() {
+                return Err(ChargebackError::AmountRequired);
+            }
+
+            let currency = tx.currency();
+            let amount = match transaction.amount {
+                Some(amount) if amount < 0.0 => {
+                    self.warn(transaction, PartnerWarning::NegativeAmount);
+                    return Ok(());
+                }
+                Some(amount) if amount > disputed => {
+                    self.warn(transaction, PartnerWarning::ExceedsDisputed);
+                    return Ok(());
+                }
+                Some(amount) => currency.round(amount),
+                None => disputed,
+            };
+
+            self.get_account_entry(&transaction.client)?;
+            let metrics = self
+                .client_metrics
+                .entry(transaction.client.clone())
+                .or_default();
+            metrics.chargebacks += 1;
+            let lock = self.chargeback_policy.should_lock(metrics);
+            if let Some(account) = self.accounts.get_mut(&transaction.client) {
+                if lock {
+                    account.lock();
+                } else {
+                    account.review = true;
+                }
+            }
+
+            // The funds are reclaimed from settlement and recovered from the
+            // client's held funds.
+            self.post(
+                transaction.tx,
+                currency,
+                LedgerAccount::ChargebackLoss,
+                LedgerAccount::Settlement,
+                amount,
+            );
+            self.post(
+                transaction.tx,
+                currency,
+                LedgerAccount::Suspense(transaction.client.clone()),
+                LedgerAccount::ChargebackLoss,
+                amount,
+            );
+
+            let mut reversed_fee = 0.0;
+            if let Some(state) = self.transaction_states.get_mut(&key) {
+                state.disputed = currency.round(state.disputed - amount);
+                state.charged_back = currency.round(state.charged_back + amount);
+
+                if let Some(original_amount) = original.amount.filter(|a| *a > 0.0) {
+                    reversed_fee = currency.round(state.fee * amount / original_amount);
+                }
+            }
+
+            if reversed_fee > 0.0 {
+                self.charge_fee(&original, amount, -reversed_fee);
+            }
+            self.save_reference(transaction);
+        } else {
+            self.warn(transaction, PartnerWarning::UnknownTx);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the fee charged for the provided transaction. Transactions
+    /// imported from bank statements aren't charged fees, since they record
+    /// movements the bank already booked, and the client's balance is
+    /// reconciled against the statement's.
+    fn fee_for(&self, transaction: &Transaction, amount: f64) -> f64 {
+        if transaction.bank_statement {
+            return 0.0;
+        }
+        self.fee_schedule
+            .fee_for(&transaction.r#type, amount, transaction.currency())
+    }
+
+    /// Transfers a fee charged for the provided transaction from the client
+    /// to the house account, in the currency of the transaction, and records
+    /// it in the fee report. Negative fees reverse a fee that was previously
+    /// charged.
+    fn charge_fee(&mut self, transaction: &Transaction, amount: f64, fee: f64) {
+        if fee == 0.0 {
+            return;
+        }
+
+        let currency = transaction.currency();
+        self.open_house_account();
+        self.post(
+            transaction.tx,
+            currency,
+            LedgerAccount::ClientLiability(transaction.client.clone()),
+            LedgerAccount::ClientLiability(self.house_account()),
+            fee,
+        );
+
+        if fee > 0.0 {
+            let state = self
+                .transaction_states
+                .entry(transaction.key())
+                .or_default();
+            state.fee = currency.round(state.fee + fee);
+        }
+
+        self.fee_records.push(FeeRecord {
+            tx: transaction.tx,
+            client: transaction.client.clone(),
+            r#type: transaction.r#type.clone(),
+            currency,
+            amount: currency.round(amount),
+            fee,
+        });
+    }
+
+    /// Process a credit limit transaction.
+    ///
+    /// A credit limit is an administrative transaction which sets the
+    /// approved credit line of the client's account to the specified amount,
+    /// in the default currency. If the client doesn't have an account yet,
+    /// the credit limit is applied once the account is created. The credit
+    /// limit may not be reduced below the amount of credit the client is
+    /// already using.
+    fn process_credit_limit(&mut self, transaction: &Transaction) -> Result<(), CreditLimitError> {
+        let currency = Currency::default();
+        let limit = match transaction.amount {
+            None => return Err(CreditLimitError::AmountRequired),
+            Some(limit) if limit < 0.0 => return Err(CreditLimitError::NegativeLimit),
+            Some(limit) => currency.round(limit),
+        };
+
+        if let Some(account) = self.accounts.get_mut(&transaction.client) {
+            // The credit line may not be reduced below what is already in use.
+            if let Some(balance) = account.balance(currency) {
+                if balance.available + limit < 0.0 {
+                    return Err(CreditLimitError::BelowCreditUsed(limit, -balance.available));
+                }
+            }
+            account.credit_limit = limit;
+        }
+
+        self.account_configs
+            .entry(transaction.client.clone())
+            .or_insert_with(|| AccountConfig {
+                client: transaction.client.clone(),
+                ..Default::default()
+            })
+            .credit_limit = limit;
+
+        Ok(())
+    }
+
+    /// Process an accrue transaction.
+    ///
+    /// An accrue is a control transaction which posts interest on the
+    /// available funds of every account, in every currency, using the
+    /// Ledger's interest schedule and the number of days in the accrual period
+    /// given as the amount. The interest is posted to each balance as a
+    /// generated deposit, with a tx id allocated downwards from u32::MAX, and
+    /// recorded in the accrual report. Locked accounts, accounts of
+    /// blocklisted clients and balances without positive available funds do
+    /// not accrue interest.
+    ///
+    /// The accrue itself is recorded like any other transaction, so that
+    /// re-sending it doesn't post the interest again.
+    fn process_accrue(&mut self, transaction: &Transaction) -> Result<(), AccrualError> {
+        let days = match transaction.amount {
+            None => return Err(AccrualError::PeriodRequired),
+            Some(days) if days < 0.0 => return Err(AccrualError::NegativePeriod),
+            Some(days) => days,
+        };
+        self.check_duplicate(transaction)?;
+
+        // Accrue in client order so that generated tx ids are deterministic.
+        // The house account holds the Ledger's own revenue, so it doesn't
+        // accrue interest.
+        let house = self.house_account();
+        let mut balances: Vec<(ClientId, Currency, f64)> = self
+            .accounts
+            .values()
+            .filter(|account| !account.locked && account.client != house)
+            .filter(|account| self.screen(&account.client).is_none())
+            .flat_map(|account| {
+                account
+                    .balances
+                    .values()
+                    .filter(|balance| balance.available > 0.0)
+                    .map(|balance| (account.client.clone(), balance.currency, balance.available))
+            })
+            .collect();
+        balances.sort_unstable_by(|(a, a_currency, _), (b, b_currency, _)| {
+            (a, a_currency).cmp(&(b, b_currency))
+        });
+
+        for (client, currency, balance) in balances {
+            let interest = self.interest_schedule.interest_for(balance, days, currency);
+            if interest <= 0.0 {
+                continue;
+            }
+
+            // Generated deposits are namespaced by their own source, so their
+            // tx ids can't collide with those of any input.
+            let deposit = Transaction {
+                date: transaction.date,
+                ..Transaction::new(
+                    TransactionType::Deposit,
+                    client.clone(),
+                    u32::MAX - self.generated_transactions,
+                    Some(interest),
+                )
+                .with_currency(currency)
+                .with_source(Arc::from(ACCRUAL_SOURCE))
+            };
+            self.check_duplicate(&deposit)?;
+            self.save_transaction(&deposit);
+            self.generated_transactions += 1;
+
+            self.post(
+                deposit.tx,
+                currency,
+                LedgerAccount::InterestExpense,
+                LedgerAccount::ClientLiability(client.clone()),
+                interest,
+            );
+
+            self.accrual_records.push(AccrualRecord {
+                run: transaction.tx,
+                tx: deposit.tx,
+                client,
+                currency,
+                balance,
+                days,
+                interest,
+            });
+        }
+        self.save_transaction(transaction);
+
+        Ok(())
+    }
+
+    /// Process a refund transaction.
+    ///
+    /// A refund is a merchant-initiated reversal of an earlier deposit. It
+    /// references the original deposit by ID and immediately removes the
+    /// refunded funds from the client's available and total funds, in the
+    /// currency of the original deposit. Unlike a chargeback, a refund does
+    /// not lock the client's account.
+    ///
+    /// A refund may specify an amount to only partially reverse the original
+    /// deposit, otherwise the entire remaining amount is refunded. Across
+    /// multiple refunds the refunded amount may not exceed the original
+    /// amount, and funds that are currently under dispute or have been charged
+    /// back may not be refunded.
+    fn process_refund(&mut self, transaction: &Transaction) -> Result<(), RefundError> {
+        let key = transaction.key();
+        let tx = match self.transactions.get(&key) {
+            Some(tx) => tx,
+            None => return Err(RefundError::NoSuchTransaction(transaction.tx)),
+        };
+
+        if tx.r#type != TransactionType::Deposit {
+            return Err(RefundError::NotADeposit(transaction.tx));
+        }
+
+        if tx.client != transaction.client {
+            return Err(RefundError::ClientMismatch(
+                transaction.tx,
+                transaction.client.clone(),
+            ));
+        }
+
+        let original = match tx.amount {
+            Some(amount) => amount,
+            None => return Err(RefundError::AmountRequired),
+        };
+
+        let currency = tx.currency();
+        let refundable = match self.transaction_states.get(&key) {
+            Some(state) => state.remaining(original, currency),
+            None => currency.round(original),
+        };
+        let amount = match transaction.amount {
+            Some(amount) if amount < 0.0 => return Err(RefundError::NegativeAmount),
+            Some(amount) if amount > refundable => {
+                return Err(RefundError::ExceedsRefundable(amount, refundable))
+            }
+            Some(amount) => currency.round(amount),
+            None => refundable,
+        };
+
+        let mut account = self.get_account_entry(&transaction.client)?;
+        let available = account.get_mut().balance_mut(currency).available;
+
+        if available - amount < 0.0 {
+            return Err(RefundError::InsufficientFunds(amount, available));
+        }
+
+        self.post(
+            transaction.tx,
+            currency,
+            LedgerAccount::ClientLiability(transaction.client.clone()),
+            LedgerAccount::Settlement,
+            amount,
+        );
+
+        let state = self.transaction_states.entry(key).or_default();
+        state.refunded = currency.round(state.refunded + amount);
+
+        Ok(())
+    }
+
+    /// Process an exchange transaction.
+    ///
+    /// An exchange debits the amount from the client's balance in the
+    /// transaction's currency and credits the converted amount to their
+    /// balance in the currency being exchanged to. The amount is converted at
+    /// the rate effective on the transaction's date, and the spread is
+    /// deducted from the converted amount and credited to the house account.
+    ///
+    /// A positive amount and a different currency to exchange to MUST be
+    /// specified, and a rate MUST be effective for the currency pair, or an
+    /// error will be returned. Locked accounts may NOT exchange funds and
+    /// exchanges may not draw on the client's credit line.
+    fn process_exchange(&mut self, transaction: &Transaction) -> Result<(), ExchangeError> {
+        let amount = match transaction.amount {
+            None => return Err(ExchangeError::AmountRequired),
+            Some(amount) if amount < 0.0 => return Err(ExchangeError::NegativeAmount),
+            Some(amount) => amount,
+        };
+
+        let from = transaction.currency();
+        let to = match transaction.to_currency {
+            None => return Err(ExchangeError::TargetCurrencyRequired),
+            Some(to) if to == from => return Err(ExchangeError::SameCurrency(to)),
+            Some(to) => to,
+        };
+
+        let date = match transaction.date.or(self.last_date) {
+            Some(date) => date,
+            None => return Err(ExchangeError::DateRequired),
+        };
+        let quote = match self.exchange_rates.quote_for(from, to, date) {
+            Some(quote) => quote,
+            None => return Err(ExchangeError::NoEffectiveRate(from, to, date)),
+        };
+        if let Some(reason) = self.screen(&transaction.client) {
+            return Err(ExchangeError::ScreeningHit(reason));
+        }
+        self.check_duplicate(transaction)?;
+
+        let amount = from.round(amount);
+        let gross = to.round(amount * quote.rate);
+        let converted = to.round(gross * (1.0 - quote.spread / 100.0));
+        let spread = to.round(gross - converted);
+
+        let mut account = self.get_account_entry(&transaction.client)?;
+        let available = account.get_mut().balance_mut(from).available;
+        if available - amount < 0.0 {
+            return Err(ExchangeError::InsufficientFunds(amount, available));
+        }
+
+        // The funds are settled in the currency exchanged from and bought in
+        // the currency exchanged to, less the spread which is transferred to
+        // the house account.
+        let client = LedgerAccount::ClientLiability(transaction.client.clone());
+        self.post(
+            transaction.tx,
+            from,
+            client.clone(),
+            LedgerAccount::Settlement,
+            amount,
+        );
+        self.post(
+            transaction.tx,
+            to,
+            LedgerAccount::Settlement,
+            client.clone(),
+            gross,
+        );
+        if spread > 0.0 {
+            self.open_house_account();
+            let house = LedgerAccount::ClientLiability(self.house_account());
+            self.post(transaction.tx, to, client, house, spread);
+        }
+
+        self.exchange_records.push(ExchangeRecord {
+            tx: transaction.tx,
+            client: transaction.client.clone(),
+            from,
+            to,
+            amount,
+            rate: quote.rate,
+            converted,
+            spread,
+        });
+        self.save_transaction(transaction);
+
+        Ok(())
+    }
+
+    /// Posts a balanced entry to the journal and updates the derived balances
+    /// of any client accounts it touches.
+    fn post(
+        &mut self,
+        tx: u32,
+        currency: Currency,
+        debit: LedgerAccount,
+        credit: LedgerAccount,
+        amount: f64,
+    ) {
+        self.journal
+            .post(tx, currency, debit.clone(), credit.clone(), amount);
+
+        for account in [debit, credit] {
+            if let LedgerAccount::ClientLiability(client) | LedgerAccount::Suspense(client) =
+                account
+            {
+                let available = self
+                    .journal
+                    .credit_balance(&LedgerAccount::ClientLiability(client.clone()), currency);
+                let held = self
+                    .journal
+                    .credit_balance(&LedgerAccount::Suspense(client.clone()), currency);
+                if let Some(account) = self.accounts.get_mut(&client) {
+                    account.balance_mut(currency).update(available, held);
+                }
+            }
+        }
+    }
+
+    /// Creates the house account that fees and spread revenue are credited
+    /// to, if it doesn't exist yet.
+    fn open_house_account(&mut self) {
+        let house = self.house_account();
+        if !self.accounts.contains_key(&house) {
+            self.accounts
+                .insert(house.clone(), Account::new_account(house));
+        }
+    }
+
+    /// Returns the account fees and spread revenue are credited to.
+    fn house_account(&self) -> ClientId {
+        self.house_account.clone().unwrap_or_else(ClientId::house)
+    }
+
+    /// Creates a new account for the provided client, configured from the
+    /// account configuration and registry. In strict mode only registered
+    /// clients may open an account.
+    fn open_account(&self, client: &ClientId) -> Result<Account, AccountError> {
+        let record = self.registered(client);
+        if self.strict_registry && record.is_none() {
+            return Err(AccountError::NoSuchAccount(client.clone()));
+        }
+
+        let mut account = Account::new_account(client.clone());
+        if let Some(config) = self.account_configs.get(client) {
+            account.credit_limit = config.credit_limit;
+        }
+        account.locked = record.is_some_and(|record| record.status().is_locked());
+
+        Ok(account)
+    }
+
+    /// Returns the registry record of the provided client, if a registry is
+    /// configured and the client is registered.
+    fn registered(&self, client: &ClientId) -> Option<&ClientRecord> {
+        self.registry
+            .as_ref()
+            .and_then(|registry| registry.get(client))
+    }
+
+    /// Screens the provided client against the blocklist, by id and by their
+    /// registered name, returning the reason for a screening hit.
+    fn screen(&self, client: &ClientId) -> Option<String> {
+        let name = self
+            .registered(client)
+            .and_then(|record| record.name.as_deref());
+        self.blocklist
+            .screen_client(client)
+            .or_else(|| name.and_then(|name| self.blocklist.screen_name(name)))
+            .map(|entry| entry.describe())
+    }
+
+    /// Fetch attempt to fetch an OccupiedEntry which contains an existing
+    /// Account.
+    fn get_account_entry(
+        &mut self,
+        id: &ClientId,
+    ) -> Result<OccupiedEntry<'_, ClientId, Account>, AccountError> {
+        match self.accounts.entry(id.clone()) {
+            Entry::Occupied(account) => {
+                if account.get().locked {
+                    return Err(AccountError::AccountLocked(id.clone()));
+                }
+
+                Ok(account)
+            }
+            Entry::Vacant(_) => Err(AccountError::NoSuchAccount(id.clone())),
+        }
+    }
+
+    /// Returns whether an identical transaction was already applied under the
+    /// same key. Dispute-family transactions share the key of the transaction
+    /// they reference, so they are recorded under their own reference key.
+    fn is_already_applied(&self, transaction: &Transaction) -> bool {
+        if transaction.is_reference() {
+            return self
+                .applied_references
+                .get(&transaction.key())
+                .is_some_and(|applied| applied.contains(&transaction.reference_key()));
+        }
+        self.transactions
+            .get(&transaction.key())
+            .is_some_and(|applied| applied == transaction)
+    }
+
+    /// Returns an error if a transaction was already applied under the key
+    /// of the provided transaction.
+    fn check_duplicate(&self, transaction: &Transaction) -> Result<(), DuplicateTransactionError> {
+        let key = transaction.key();
+        if self.transactions.contains_key(&key) {
+            return Err(DuplicateTransactionError::new(key));
+        }
+
+        Ok(())
+    }
+
+    /// Saves a transaction once it has been applied, so that it can be
+    /// disputed and its re-sends are recognized. Transactions that were
+    /// rejected aren't saved, and are evaluated again if they are re-sent.
+    fn save_transaction(&mut self, transaction: &Transaction) {
+        if let Some(undo) = self.undo.as_mut() {
+            undo.transactions.push(transaction.key());
+        }
+        self.transactions
+            .insert(transaction.key(), transaction.clone());
+    }
+
+    /// Records an applied dispute, resolve or chargeback, so that its
+    /// re-sends are recognized while the dispute it belongs to is open. Once
+    /// a resolve or chargeback closes the dispute the records are cleared, so
+    /// that the transaction can be disputed again.
+    fn save_reference(&mut self, transaction: &Transaction) {
+        let key = transaction.key();
+        let open = self
+            .transaction_states
+            .get(&key)
+            .is_some_and(|state| state.is_disputed());
+        if open {
+            self.applied_references
+                .entry(key)
+                .or_default()
+                .insert(transaction.reference_key());
+        } else {
+            self.applied_references.remove(&key);
+        }
+    }
+}
+
+impl TryFrom<PathBuf> for Ledger {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to parse the CSV file located at the provided PathBuf and
+    /// streams the data into a newly allocated Ledger.
+    ///
+    /// Any errors encountered while decoding CSV rows or during transaction
+    /// processing are returned immediately and the stream is closed.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        // Allocate a new mutable ledger that we can populate from decoded CSV
+        // transactions.
+        let mut ledger = Ledger::default();
+        ledger.process_file(path)?;
+
+        Ok(ledger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interest::DayCount;
+    use crate::limits::LimitKind;
+    use crate::risk::{DepositAfterChargeback, LargeDepositWithdrawal, RepeatedDisputes};
+    use crate::screening::Blocklist;
+
+    /// Builds the key of a transaction that wasn't ingested from a named
+    /// source.
+    fn key(tx: u32) -> TransactionKey {
+        TransactionKey { source: None, tx }
+    }
+
+    /// Builds the balances of an account which only holds funds in the
+    /// default currency.
+    fn usd(available: f64, held: f64, total: f64) -> BTreeMap<Currency, Balance> {
+        let currency = Currency::default();
+        BTreeMap::from([(
+            currency,
+            Balance {
+                currency,
+                available,
+                held,
+                total,
+            },
+        )])
+    }
+
+    impl Ledger {
+        pub fn usd_balance(&self, client: &ClientId) -> &Balance {
+            self.accounts[client].balance(Currency::default()).unwrap()
+        }
+
+        pub fn lock_account(&mut self, id: &ClientId) {
+            let mut account = self.get_account_entry(id).unwrap();
+            let account = account.get_mut();
+
+            account.lock();
+        }
+
+        pub fn is_disputed(&self, tx: u32) -> bool {
+            self.transaction_states
+                .get(&key(tx))
+                .is_some_and(TransactionState::is_disputed)
+        }
+
+        pub fn process_transactions(
+            &mut self,
+            transactions: Vec<Transaction>,
+        ) -> Result<(), Box<dyn Error>> {
+            for tx in transactions.iter() {
+                self.process_transaction(tx)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_fail_to_make_deposit_with_no_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and process a new deposit transaction with no amount set and
+        // assert that the transaction fails with the expected error.
+        let tx = Transaction::new(TransactionType::Deposit, client.clone(), 1, None);
+        assert_eq!(
+            ledger.process_transaction(&tx).unwrap_err().to_string(),
+            DepositError::AmountRequired.to_string()
+        );
+    }
+
+    #[test]
+    fn should_fail_to_deposit_negative_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and process a new negative deposit transaction and assert
+        // that the transaction fails as expected.
+        let tx = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(-1.0));
+
+        assert_eq!(
+            ledger.process_transaction(&tx).unwrap_err().to_string(),
+            DepositError::NegativeDeposit.to_string()
+        );
+
+        // Assert that we did not create a new account for the invalid deposit.
+        assert_eq!(ledger.accounts.get(&client), None);
+    }
+
+    #[test]
+    fn should_deposit_funds_to_new_account() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and process a new deposit transaction and assert that the
+        // transaction was processed successfully.
+        let tx = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        let result = ledger.process_transaction(&tx);
+        assert!(result.is_ok());
+
+        // Now assert that the account was created properly and that the
+        // account contains the correct balance.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(1.0, 0.0, 1.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_deposit_duplicate_transaction() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and publish the first transaction with id=1 and verify that
+        // it processes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(1.0, 0.0, 1.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+
+        // Create and publish a second transaction with id=1 but a different
+        // amount and verify that the expected error is returned.
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 1u32, Some(2.0));
+        assert_eq!(
+            ledger.process_transaction(&tx2).unwrap_err().to_string(),
+            DepositError::DuplicateTx(DuplicateTransactionError::new(TransactionKey {
+                source: None,
+                tx: 1
+            }))
+            .to_string()
+        );
+
+        // Now verify that only the first deposit resulted in modifications to
+        // the specified account.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(1.0, 0.0, 1.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_deposit_multiple_transactions() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process two transactions and verify that they complete successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(1.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+
+        // Now verify the state of the account the deposits were made on.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(2.0, 0.0, 2.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_deposit_to_a_locked_account() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Now lock the account and attempt to make another deposit and verify
+        // that the deposit fails with the expected error.
+        ledger.lock_account(&client);
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(1.0));
+        assert_eq!(
+            ledger.process_transaction(&tx2).unwrap_err().to_string(),
+            DepositError::AccountLocked.to_string()
+        );
+    }
+
+    #[test]
+    fn should_fail_to_make_withdrawal_with_no_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and process a new withdrawal transaction with no amount set
+        // and assert that the transaction fails with the expected error.
+        let tx = Transaction::new(TransactionType::Withdrawal, client.clone(), 1, None);
+        assert_eq!(
+            ledger.process_transaction(&tx).unwrap_err().to_string(),
+            WithdrawalError::AmountRequired.to_string()
+        );
+    }
+
+    #[test]
+    fn should_fail_to_withdraw_negative_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create and process a new negative withdrawal transaction and assert
+        // that the transaction fails as expected.
+        let tx = Transaction::new(TransactionType::Withdrawal, client.clone(), 1, Some(-1.0));
+
+        assert_eq!(
+            ledger.process_transaction(&tx).unwrap_err().to_string(),
+            WithdrawalError::NegativeWithdrawal.to_string()
+        );
+    }
+
+    #[test]
+    fn should_fail_to_withdraw_from_a_locked_account() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Now lock the account and attempt to make a withdrawal and verify
+        // that the transaction fails with the expected error.
+        ledger.lock_account(&client);
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(1.0));
+        assert_eq!(
+            ledger.process_transaction(&tx2).unwrap_err().to_string(),
+            WithdrawalError::AccountLocked.to_string()
+        );
+    }
+
+    #[test]
+    fn should_fail_to_withdraw_from_an_account_with_insufficient_funds() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Now attempt to withdraw more money than we just deposited and verify
+        // the transaction succeeds, but doesn't complete the withdrawal.
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(20.0));
+        assert!(ledger.process_transaction(&tx2).is_ok());
+
+        // Now verify the state of the account the withdrawals failed on due to
+        // insufficient funds.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(10.0, 0.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_withdraw_from_an_unknown_account() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Attempt to withdraw money from an unknown account.
+        let tx1 = Transaction::new(TransactionType::Withdrawal, client.clone(), 1, Some(20.0));
+        assert_eq!(
+            ledger.process_transaction(&tx1).unwrap_err().to_string(),
+            WithdrawalError::NoSuchAccount(client).to_string()
+        );
+    }
+
+    #[test]
+    fn should_withdraw_from_account() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        for tx_id in 1..10 {
+            // Need to add 1 because we created tx 1 above.
+            let tx = Transaction::new(
+                TransactionType::Withdrawal,
+                client.clone(),
+                tx_id + 1,
+                Some(1.0),
+            );
+            assert!(ledger.process_transaction(&tx).is_ok());
+        }
+
+        // Now verify the state of the account the withdrawals were made from.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(1.0, 0.0, 1.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_dispute_a_dispute() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Manually insert a dispute transaction.
+        //
+        // Note: We must manually insert this transaction since we _shouldn't_
+        // otherwise be able to end up with an existing transaction that has no
+        // amount.
+        ledger.transactions.insert(
+            key(2),
+            Transaction::new(TransactionType::Dispute, client.clone(), 1, None),
+        );
+
+        // Now attempt to dispute the dispute (tx with no amount) and verify
+        // that the transaction fails.
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        assert_eq!(
+            ledger.process_transaction(&tx3).unwrap_err().to_string(),
+            DisputeError::AmountRequired.to_string()
+        );
+    }
+
+    #[test]
+    fn should_dispute_a_deposit() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Now dispute that deposit and verify that we complete successfully
+        // and that the ledger shows the transaction is disputed.
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        assert!(ledger.process_transaction(&tx2).is_ok());
+        assert!(ledger.is_disputed(1));
+    }
+
+    #[test]
+    fn should_dispute_a_withdrawal() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a couple transactions and verify that they complete
+        // successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(5.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+
+        // Now dispute the withdrawal and verify that we complete successfully
+        // and that the ledger shows the transaction is disputed.
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        assert!(ledger.process_transaction(&tx3).is_ok());
+        assert!(ledger.is_disputed(2));
+    }
+
+    #[test]
+    fn should_resolve_a_disputed_transaction() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds into a new account, dispute the deposit, and then
+        // resolve the dispute and assert the
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        let tx3 = Transaction::new(TransactionType::Resolve, client.clone(), 1, None);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Now assert that the account has the expected balance and that the
+        // transaction is no longer disputed.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(10.0, 0.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_resolve_a_transaction_with_no_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Manually insert a dispute transaction and mark it as disputed.
+        //
+        // Note: We must manually insert this transaction since we _shouldn't_
+        // otherwise be able to end up with an existing transaction that has no
+        // amount.
+        ledger.transactions.insert(
+            key(2),
+            Transaction::new(TransactionType::Dispute, client.clone(), 1, None),
+        );
+        ledger.transaction_states.insert(
+            key(2),
+            TransactionState {
+                disputed: 1.0,
+                charged_back: 0.0,
+                refunded: 0.0,
+                fee: 0.0,
+            },
+        );
+
+        // Now attempt to dispute the dispute (tx with no amount) and verify
+        // that the transaction fails.
+        let tx3 = Transaction::new(TransactionType::Resolve, client.clone(), 2, None);
+        assert_eq!(
+            ledger.process_transaction(&tx3).unwrap_err().to_string(),
+            ResolveError::AmountRequired.to_string()
+        );
+    }
+
+    #[test]
+    fn should_chargeback_a_disputed_transaction() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Create two deposits, dispute the largest deposit, and then issue a
+        // chargeback and verify that the result is successful.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(1000.0));
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        let tx4 = Transaction::new(TransactionType::Chargeback, client.clone(), 2, None);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3, tx4]))
+            .is_ok());
+
+        // Now verify that the account shows the correct balance.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(10.0, 0.0, 10.0),
+                locked: true,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_chargeback_a_transaction_with_no_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Process a deposit and verify that it completes successfully.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_transaction(&tx1).is_ok());
+
+        // Manually insert a dispute transaction and mark it as disputed.
+        //
+        // Note: We must manually insert this transaction since we _shouldn't_
+        // otherwise be able to end up with an existing transaction that has no
+        // amount.
+        ledger.transactions.insert(
+            key(2),
+            Transaction::new(TransactionType::Dispute, client.clone(), 1, None),
+        );
+        ledger.transaction_states.insert(
+            key(2),
+            TransactionState {
+                disputed: 1.0,
+                charged_back: 0.0,
+                refunded: 0.0,
+                fee: 0.0,
+            },
+        );
+
+        // Now attempt to chargeback the dispute (tx with no amount) and verify
+        // that the transaction fails.
+        let tx3 = Transaction::new(TransactionType::Chargeback, client.clone(), 2, None);
+        assert_eq!(
+            ledger.process_transaction(&tx3).unwrap_err().to_string(),
+            ChargebackError::AmountRequired.to_string()
+        );
+    }
+
+    #[test]
+    fn should_round_values() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit a value with more than 4 decimal points and verify that the
+        // stored value is rounded to 4.
+        let tx = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(8.675309));
+        assert!(ledger.process_transaction(&tx).is_ok());
+
+        // Now verify that the account shows the correct balance.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(8.6753, 0.0, 8.6753),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_generate_statement_report() {
+        // Create a new ledger.
+        let mut ledger = Ledger::default();
+
+        // Deposit some values into the ledger.
+        let tx1 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(1),
+            1,
+            Some(10.0),
+        );
+        let tx2 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(2),
+            2,
+            Some(20.0),
+        );
+        let tx3 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(3),
+            3,
+            Some(30.0),
+        );
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Now verify that the account shows the correct balance.
+        let result = ledger.generate_account_statements();
+        assert!(result.is_ok());
+
+        // We don't guarantee a sort order for the output, so simply assert
+        // that the lines we expect to see are present in the output.
+        let output = result.unwrap();
+        assert!(output.starts_with(
+            "client,currency,available,held,total,locked,review,negative_balance,owed,credit_limit,credit_used\n"
+        ));
+        let expected_lines = [
+            "1,USD,10.0,0.0,10.0,false,false,false,0.0,0.0,0.0\n",
+            "2,USD,20.0,0.0,20.0,false,false,false,0.0,0.0,0.0\n",
+            "3,USD,30.0,0.0,30.0,false,false,false,0.0,0.0,0.0\n",
+        ];
+        for line in expected_lines {
+            assert!(output.contains(line));
+        }
+    }
+
+    #[test]
+    fn should_partially_dispute_a_deposit() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds and then dispute only part of that deposit.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(4.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+
+        // Verify that only the disputed part of the deposit is held.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(6.0, 4.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+        assert_eq!(
+            ledger.transaction_states.get(&key(1)),
+            Some(&TransactionState {
+                disputed: 4.0,
+                charged_back: 0.0,
+                refunded: 0.0,
+                fee: 0.0,
+            })
+        );
+
+        // Disputing the remainder of the deposit without an amount should
+        // only hold what hasn't already been disputed.
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        assert!(ledger.process_transaction(&tx3).is_ok());
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(0.0, 10.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_fail_to_dispute_more_than_the_undisputed_amount() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds and dispute part of that deposit.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(7.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+
+        // Now attempt to dispute more than what remains of the deposit and
+        // verify that the dispute is ignored with a partner warning, without
+        // holding any more funds.
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(5.0));
+        assert!(ledger.process_transaction(&tx3).is_ok());
+        assert_eq!(
+            ledger.warnings[0].warning,
+            PartnerWarning::ExceedsDisputable
+        );
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(3.0, 7.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn should_partially_resolve_a_disputed_transaction() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds, dispute part of the deposit and then resolve part of
+        // that dispute.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(6.0));
+        let tx3 = Transaction::new(TransactionType::Resolve, client.clone(), 1, Some(2.5));
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Verify that only the resolved funds were released and that the
+        // remainder is still under dispute.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(6.5, 3.5, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+        assert!(ledger.is_disputed(1));
+
+        // Resolving more than is still disputed is ignored as a partner
+        // warning.
+        let tx4 = Transaction::new(TransactionType::Resolve, client.clone(), 1, Some(4.0));
+        assert!(ledger.process_transaction(&tx4).is_ok());
+        assert_eq!(ledger.usd_balance(&client).held, 3.5);
+        assert_eq!(ledger.warnings[0].warning, PartnerWarning::ExceedsDisputed);
+    }
+
+    #[test]
+    fn should_partially_chargeback_a_disputed_transaction() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds, dispute the deposit and then charge back part of the
+        // disputed amount.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        let tx3 = Transaction::new(TransactionType::Chargeback, client.clone(), 1, Some(4.0));
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Verify that only the charged back funds left the held balance and
+        // that the transaction state reflects the partial chargeback.
+        assert_eq!(
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(0.0, 6.0, 6.0),
+                locked: true,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+        assert_eq!(
+            ledger.transaction_states.get(&key(1)),
+            Some(&TransactionState {
+                disputed: 6.0,
+                charged_back: 4.0,
+                refunded: 0.0,
+                fee: 0.0,
+            })
+        );
+    }
+
+    /// Deposits funds, withdraws all of them and then disputes the deposit
+    /// under the provided NegativeBalancePolicy.
+    fn dispute_spent_deposit(
+        policy: NegativeBalancePolicy,
+    ) -> (Ledger, Result<(), Box<dyn Error>>) {
+        let mut ledger = Ledger::default().with_negative_balance_policy(policy);
+        let client = ClientId::Numeric(1);
+
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(10.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        let result = ledger.process_transaction(&tx3);
+        (ledger, result)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn should_reject_dispute_of_spent_deposit() {
+        let (ledger, result) = dispute_spent_deposit(NegativeBalancePolicy::Reject);
 
-    impl Ledger {
-        pub fn lock_account(&mut self, id: u16) {
-            let mut account = self.get_account_entry(id).unwrap();
-            let account = account.get_mut();
+        // Verify that the dispute was rejected without stopping processing,
+        // reported to the partner, and that nothing was held.
+        assert!(result.is_ok());
+        assert_eq!(ledger.warnings.len(), 1);
+        assert_eq!(
+            ledger.warnings[0].warning,
+            PartnerWarning::InsufficientFunds
+        );
+        assert!(!ledger.is_disputed(1));
+        assert_eq!(
+            ledger.accounts.get(&ClientId::Numeric(1)),
+            Some(&Account {
+                client: ClientId::Numeric(1),
+                balances: usd(0.0, 0.0, 0.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
+        );
+    }
 
-            account.lock();
-        }
+    #[test]
+    fn should_allow_debt_when_disputing_spent_deposit() {
+        let (ledger, result) = dispute_spent_deposit(NegativeBalancePolicy::AllowDebt);
 
-        pub fn process_transactions(
-            &mut self,
-            transactions: Vec<Transaction>,
-        ) -> Result<(), Box<dyn Error>> {
-            for tx in transactions.iter() {
-                self.process_transaction(tx)?;
-            }
+        // Verify that the full amount was held, driving available funds
+        // negative, and that the statement flags the debt.
+        assert!(result.is_ok());
+        assert!(ledger.is_disputed(1));
+
+        let balance = ledger.usd_balance(&ClientId::Numeric(1));
+        assert_eq!(balance.available, -10.0);
+        assert_eq!(balance.held, 10.0);
+
+        let statement = ledger.accounts[&ClientId::Numeric(1)]
+            .statements()
+            .next()
+            .unwrap();
+        assert!(statement.negative_balance);
+        assert_eq!(statement.owed, 10.0);
+    }
 
-            Ok(())
-        }
+    #[test]
+    fn should_hold_only_available_funds_when_disputing_spent_deposit() {
+        let mut ledger =
+            Ledger::default().with_negative_balance_policy(NegativeBalancePolicy::HoldAvailable);
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds, withdraw most of them and then dispute the deposit.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(7.0));
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Verify that only the remaining available funds were held.
+        let balance = ledger.usd_balance(&client);
+        assert_eq!(balance.available, 0.0);
+        assert_eq!(balance.held, 3.0);
+        assert_eq!(
+            ledger.transaction_states.get(&key(1)),
+            Some(&TransactionState {
+                disputed: 3.0,
+                charged_back: 0.0,
+                refunded: 0.0,
+                fee: 0.0,
+            })
+        );
+        assert!(
+            !ledger.accounts[&client]
+                .statements()
+                .next()
+                .unwrap()
+                .negative_balance
+        );
     }
 
     #[test]
-    fn should_fail_to_make_deposit_with_no_amount() {
+    fn should_keep_total_equal_to_available_plus_held() {
         // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
+        let mut ledger = Ledger::default().with_verification(true);
+        let client = ClientId::Numeric(1);
 
-        // Create and process a new deposit transaction with no amount set and
-        // assert that the transaction fails with the expected error.
-        let tx = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: None,
-        };
+        // Deposit funds and dispute part of the deposit, then verify that the
+        // total funds did not change.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(4.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
         assert_eq!(
-            ledger.process_transaction(&tx).unwrap_err().to_string(),
-            DepositError::AmountRequired.to_string()
+            ledger.accounts.get(&client),
+            Some(&Account {
+                client: client.clone(),
+                balances: usd(6.0, 4.0, 10.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
+            })
         );
+        assert!(ledger.verify().is_ok());
     }
 
     #[test]
-    fn should_fail_to_deposit_negative_amount() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
+    fn should_detect_invariant_violations() {
+        let policy = NegativeBalancePolicy::Reject;
 
-        // Create and process a new negative deposit transaction and assert
-        // that the transaction fails as expected.
-        let tx = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(-1.0),
+        // An account whose total doesn't match its available and held funds.
+        let account = Account {
+            client: ClientId::Numeric(1),
+            balances: usd(6.0, 4.0, 2.0),
+            locked: false,
+            review: false,
+            credit_limit: 0.0,
         };
+        assert_eq!(
+            account.check_invariants(policy),
+            Err(InvariantViolation::TotalMismatch(
+                ClientId::Numeric(1),
+                6.0,
+                4.0,
+                2.0
+            ))
+        );
 
+        // An account with negative held funds.
+        let account = Account {
+            client: ClientId::Numeric(1),
+            balances: usd(6.0, -4.0, 2.0),
+            locked: false,
+            review: false,
+            credit_limit: 0.0,
+        };
         assert_eq!(
-            ledger.process_transaction(&tx).unwrap_err().to_string(),
-            DepositError::NegativeDeposit.to_string()
+            account.check_invariants(policy),
+            Err(InvariantViolation::NegativeHeld(ClientId::Numeric(1), -4.0))
         );
 
-        // Assert that we did not create a new account for the invalid deposit.
-        assert_eq!(ledger.accounts.get(&client), None);
+        // An account with negative total funds is only valid if the policy
+        // allows clients to go into debt.
+        let account = Account {
+            client: ClientId::Numeric(1),
+            balances: usd(-10.0, 0.0, -10.0),
+            locked: true,
+            review: false,
+            credit_limit: 0.0,
+        };
+        assert_eq!(
+            account.check_invariants(policy),
+            Err(InvariantViolation::NegativeTotal(
+                ClientId::Numeric(1),
+                -10.0
+            ))
+        );
+        assert!(account
+            .check_invariants(NegativeBalancePolicy::AllowDebt)
+            .is_ok());
     }
 
     #[test]
-    fn should_deposit_funds_to_new_account() {
+    fn should_partially_refund_a_deposit() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
 
-        // Create and process a new deposit transaction and assert that the
-        // transaction was processed successfully.
-        let tx = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        let result = ledger.process_transaction(&tx);
-        assert!(result.is_ok());
+        // Deposit funds and then refund part of that deposit twice.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Refund, client.clone(), 1, Some(3.0));
+        let tx3 = Transaction::new(TransactionType::Refund, client.clone(), 1, Some(2.0));
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
 
-        // Now assert that the account was created properly and that the
-        // account contains the correct balance.
+        // Verify that the refunded funds were removed and that the account
+        // was not locked.
         assert_eq!(
             ledger.accounts.get(&client),
             Some(&Account {
-                client,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
-                locked: false
+                client: client.clone(),
+                balances: usd(5.0, 0.0, 5.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
             })
         );
+
+        // Refunding more than what remains of the deposit should fail.
+        let tx4 = Transaction::new(TransactionType::Refund, client.clone(), 1, Some(6.0));
+        assert_eq!(
+            ledger.process_refund(&tx4).unwrap_err().to_string(),
+            RefundError::ExceedsRefundable(6.0, 5.0).to_string()
+        );
+
+        // Refunding without an amount refunds whatever remains.
+        let tx5 = Transaction::new(TransactionType::Refund, client.clone(), 1, None);
+        assert!(ledger.process_transaction(&tx5).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 0.0);
     }
 
     #[test]
-    fn should_fail_to_deposit_duplicate_transaction() {
+    fn should_not_refund_disputed_funds() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
 
-        // Create and publish the first transaction with id=1 and verify that
-        // it processes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
-        assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
-                locked: false
-            })
-        );
+        // Deposit funds and dispute part of the deposit.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(6.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
-        // Create and publish a second transaction with id=1 and verify that
-        // the expected error is returned.
-        let tx2 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1u32,
-            amount: Some(1.0),
-        };
+        // Only the undisputed part of the deposit may be refunded.
+        let tx3 = Transaction::new(TransactionType::Refund, client.clone(), 1, Some(5.0));
         assert_eq!(
-            ledger.process_transaction(&tx2).unwrap_err().to_string(),
-            DepositError::DuplicateTx(DuplicateTransactionError::new(1)).to_string()
+            ledger.process_refund(&tx3).unwrap_err().to_string(),
+            RefundError::ExceedsRefundable(5.0, 4.0).to_string()
         );
 
-        // Now verify that only the first deposit resulted in modifications to
-        // the specified account.
+        // Refund the remainder and verify that it can no longer be disputed.
+        let tx4 = Transaction::new(TransactionType::Refund, client.clone(), 1, None);
+        assert!(ledger.process_transaction(&tx4).is_ok());
         assert_eq!(
             ledger.accounts.get(&client),
             Some(&Account {
-                client,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
-                locked: false
+                client: client.clone(),
+                balances: usd(0.0, 6.0, 6.0),
+                locked: false,
+                review: false,
+                credit_limit: 0.0,
             })
         );
+
+        let tx5 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(1.0));
+        assert!(ledger.process_dispute(&tx5).is_ok());
+        assert_eq!(ledger.warnings[0].warning, PartnerWarning::AlreadyDisputed);
     }
 
     #[test]
-    fn should_deposit_multiple_transactions() {
+    fn should_fail_to_refund_a_withdrawal() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
 
-        // Process two transactions and verify that they complete successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        let tx2 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 2,
-            amount: Some(1.0),
-        };
+        // Deposit funds and withdraw some of them.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(5.0));
         assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
-        // Now verify the state of the account the deposits were made on.
+        // Only deposits may be refunded.
+        let tx3 = Transaction::new(TransactionType::Refund, client.clone(), 2, None);
+        assert_eq!(
+            ledger.process_refund(&tx3).unwrap_err().to_string(),
+            RefundError::NotADeposit(2).to_string()
+        );
+
+        // When ingested, the malformed refund is skipped without stopping
+        // processing or touching the client's funds.
+        assert!(ledger.process_transaction(&tx3).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 5.0);
+    }
+
+    #[test]
+    fn should_withdraw_into_credit_line() {
+        // Create a ledger and declare a client id to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds, approve a credit line and withdraw more than the
+        // deposited funds.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::CreditLimit, client.clone(), 2, Some(50.0));
+        let tx3 = Transaction::new(TransactionType::Withdrawal, client.clone(), 3, Some(40.0));
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Verify the account balance and the reported credit utilization.
+        let account = ledger.accounts.get(&client).unwrap();
+        assert_eq!(
+            account,
+            &Account {
+                client: client.clone(),
+                balances: usd(-30.0, 0.0, -30.0),
+                locked: false,
+                review: false,
+                credit_limit: 50.0,
+            }
+        );
+        let statement = account.statements().next().unwrap();
+        assert_eq!(statement.credit_limit, 50.0);
+        assert_eq!(statement.credit_used, 30.0);
+
+        // Withdrawing beyond the credit line should fail and report the
+        // limit alongside the available funds.
+        let tx4 = Transaction::new(TransactionType::Withdrawal, client.clone(), 4, Some(25.0));
+        assert_eq!(
+            ledger.process_withdrawal(&tx4).unwrap_err().to_string(),
+            WithdrawalError::InsufficientFunds(25.0, -30.0, 50.0).to_string()
+        );
+
+        // The credit line may not be reduced below what is in use.
+        let tx5 = Transaction::new(TransactionType::CreditLimit, client.clone(), 5, Some(20.0));
+        assert_eq!(
+            ledger.process_transaction(&tx5).unwrap_err().to_string(),
+            CreditLimitError::BelowCreditUsed(20.0, 30.0).to_string()
+        );
+    }
+
+    #[test]
+    fn should_apply_account_config_to_new_accounts() {
+        // Create a ledger with a configured credit line for a client that
+        // doesn't have an account yet.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+        ledger.account_configs.insert(
+            client.clone(),
+            AccountConfig {
+                client: client.clone(),
+                credit_limit: 5.0,
+                tier: None,
+            },
+        );
+
+        // Deposit funds, opening the account, and withdraw into the credit
+        // line.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(6.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
         assert_eq!(
             ledger.accounts.get(&client),
             Some(&Account {
-                client,
-                available: 2.0,
-                held: 0.0,
-                total: 2.0,
-                locked: false
+                client: client.clone(),
+                balances: usd(-5.0, 0.0, -5.0),
+                locked: false,
+                review: false,
+                credit_limit: 5.0,
             })
         );
     }
 
     #[test]
-    fn should_fail_to_deposit_to_a_locked_account() {
-        // Create a ledger and declare a client id to use.
+    fn should_reject_rows_for_the_house_account() {
+        // Create a ledger which charges a 1% deposit fee, crediting fees to
+        // house account 0.
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\ndeposit,,,1,,\n".as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_fee_schedule(schedule, ClientId::Numeric(0));
+
+        // A row for the house account is rejected without stopping the run,
+        // so it neither collects nor is exempt from fees.
+        let data = "type,client,tx,amount\n\
+                    deposit,0,1,100.0\n\
+                    deposit,1,2,100.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(0)).total, 1.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 99.0);
+        assert!(!ledger.transactions.contains_key(&key(1)));
+
+        // The same goes for the reserved house id, which is used by default.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let tx = Transaction::new(TransactionType::Deposit, ClientId::house(), 1, Some(100.0));
+        assert!(ledger.process_transaction(&tx).is_ok());
+        assert!(ledger.accounts.is_empty());
+    }
 
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+    #[test]
+    fn should_charge_and_reverse_fees() {
+        // Create a ledger which charges a 1% deposit fee and a flat
+        // withdrawal fee, crediting fees to house account 0.
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\n\
+             deposit,,,1,,\n\
+             withdrawal,,0.5,,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_fee_schedule(schedule, ClientId::Numeric(0));
+        let client = ClientId::Numeric(1);
+
+        // Deposit funds and withdraw some of them.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(100.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(10.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
-        // Now lock the account and attempt to make another deposit and verify
-        // that the deposit fails with the expected error.
-        ledger.lock_account(client);
-        let tx2 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 2,
-            amount: Some(1.0),
-        };
+        // Verify that the fees were deducted from the client and credited to
+        // the house account.
+        assert_eq!(ledger.usd_balance(&client).total, 88.5);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(0)).total, 1.5);
+
+        // Withdrawals that can't cover their fee should fail.
+        let tx3 = Transaction::new(TransactionType::Withdrawal, client.clone(), 3, Some(88.5));
         assert_eq!(
-            ledger.process_transaction(&tx2).unwrap_err().to_string(),
-            DepositError::AccountLocked.to_string()
+            ledger.process_withdrawal(&tx3).unwrap_err().to_string(),
+            WithdrawalError::InsufficientFunds(89.0, 88.5, 0.0).to_string()
+        );
+
+        // Now dispute and charge back half of the deposit and verify that
+        // half of the deposit fee is reversed.
+        let tx4 = Transaction::new(TransactionType::Dispute, client.clone(), 1, Some(50.0));
+        let tx5 = Transaction::new(TransactionType::Chargeback, client.clone(), 1, None);
+        assert!(ledger.process_transactions(Vec::from([tx4, tx5])).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 39.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(0)).total, 1.0);
+
+        // Deposits into the house account itself are rejected.
+        let tx6 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(0),
+            6,
+            Some(10.0),
+        );
+        assert!(ledger.process_transaction(&tx6).is_ok());
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(0)).total, 1.0);
+
+        // Verify the itemized fee report.
+        let report = ledger.generate_fee_report().unwrap();
+        assert_eq!(
+            report,
+            "tx,client,type,currency,amount,fee\n\
+             1,1,deposit,USD,100.0,1.0\n\
+             2,1,withdrawal,USD,10.0,0.5\n\
+             1,1,deposit,USD,50.0,-0.5\n"
         );
     }
 
     #[test]
-    fn should_fail_to_make_withdrawal_with_no_amount() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
+    fn should_skip_deposits_that_cannot_cover_their_fee() {
+        // Create a ledger which charges a flat deposit fee.
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\n\
+             deposit,,1,,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_fee_schedule(schedule, ClientId::Numeric(0));
+        let client = ClientId::Numeric(1);
+
+        // A deposit smaller than its fee is skipped without stopping
+        // processing.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(0.5));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(5.0));
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 4.0);
+        assert_eq!(
+            ledger.generate_fee_report().unwrap(),
+            "tx,client,type,currency,amount,fee\n\
+             2,1,deposit,USD,5.0,1.0\n"
+        );
+    }
 
-        // Create and process a new withdrawal transaction with no amount set
-        // and assert that the transaction fails with the expected error.
-        let tx = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 1,
-            amount: None,
+    #[test]
+    fn should_accrue_interest_on_unlocked_accounts() {
+        // Create a ledger which pays 10% a year on available balances.
+        let schedule =
+            InterestSchedule::from_reader("threshold,rate\n0,10\n".as_bytes(), DayCount::Actual360)
+                .unwrap();
+        // Deposits are charged a flat fee, which is credited to the house
+        // account.
+        let fees = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\ndeposit,,10,,,\n".as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default()
+            .with_interest_schedule(schedule)
+            .with_fee_schedule(fees, ClientId::house());
+
+        // Deposit funds into two accounts and lock the second one.
+        let tx1 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(1),
+            1,
+            Some(1010.0),
+        );
+        let tx2 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(2),
+            2,
+            Some(1010.0),
+        );
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+        ledger.lock_account(&ClientId::Numeric(2));
+
+        // Accrue interest for a 36 day period.
+        let tx3 = Transaction::new(TransactionType::Accrue, ClientId::Numeric(0), 3, Some(36.0));
+        assert!(ledger.process_transaction(&tx3).is_ok());
+
+        // Verify that only the unlocked client account accrued interest, and
+        // that it was posted as a generated deposit in its own namespace.
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 1010.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(2)).total, 1000.0);
+        assert_eq!(ledger.usd_balance(&ClientId::house()).total, 20.0);
+        let generated = TransactionKey {
+            source: Some(Arc::from(ACCRUAL_SOURCE)),
+            tx: u32::MAX,
         };
         assert_eq!(
-            ledger.process_transaction(&tx).unwrap_err().to_string(),
-            WithdrawalError::AmountRequired.to_string()
+            ledger.transactions.get(&generated).unwrap().amount,
+            Some(10.0)
+        );
+        assert!(!ledger.transactions.contains_key(&key(u32::MAX)));
+        assert_eq!(
+            ledger.accrual_records,
+            Vec::from([AccrualRecord {
+                run: 3,
+                tx: u32::MAX,
+                client: ClientId::Numeric(1),
+                currency: Currency::default(),
+                balance: 1000.0,
+                days: 36.0,
+                interest: 10.0,
+            }])
         );
     }
 
     #[test]
-    fn should_fail_to_withdraw_negative_amount() {
-        // Create a ledger and declare a client id to use.
+    fn should_hold_balances_per_currency() {
+        // Create a ledger and declare a client id and currencies to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
+        let jpy: Currency = "JPY".parse().unwrap();
+        let bhd: Currency = "BHD".parse().unwrap();
+
+        // Deposit funds in the default currency, in JPY and in BHD, then
+        // withdraw some of the JPY.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.5));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(1000.4))
+            .with_currency(jpy);
+        let tx3 = Transaction::new(TransactionType::Deposit, client.clone(), 3, Some(1.2345))
+            .with_currency(bhd);
+        let tx4 = Transaction::new(TransactionType::Withdrawal, client.clone(), 4, Some(200.0))
+            .with_currency(jpy);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3, tx4]))
+            .is_ok());
 
-        // Create and process a new negative withdrawal transaction and assert
-        // that the transaction fails as expected.
-        let tx = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 1,
-            amount: Some(-1.0),
-        };
+        // Verify that each currency has its own balance, rounded to the
+        // precision of the currency.
+        let account = ledger.accounts.get(&client).unwrap();
+        assert_eq!(ledger.usd_balance(&client).total, 10.5);
+        assert_eq!(account.balance(jpy).unwrap().total, 800.0);
+        assert_eq!(account.balance(bhd).unwrap().total, 1.235);
+
+        // Withdrawing more JPY than the client holds should fail even though
+        // the client has funds in other currencies.
+        let tx5 = Transaction::new(TransactionType::Withdrawal, client.clone(), 5, Some(801.0))
+            .with_currency(jpy);
+        assert!(ledger.process_withdrawal(&tx5).is_err());
+
+        // The statement report contains one row per currency.
+        let output = ledger.generate_account_statements().unwrap();
+        let expected_lines = [
+            "1,BHD,1.235,0.0,1.235,false,false,false,0.0,0.0,0.0\n",
+            "1,JPY,800.0,0.0,800.0,false,false,false,0.0,0.0,0.0\n",
+            "1,USD,10.5,0.0,10.5,false,false,false,0.0,0.0,0.0\n",
+        ];
+        for line in expected_lines {
+            assert!(output.contains(line));
+        }
+    }
+
+    #[test]
+    fn should_dispute_in_original_currency() {
+        // Create a ledger and declare a client id and currency to use.
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+        let eur: Currency = "EUR".parse().unwrap();
+
+        // Deposit funds in USD and EUR, then dispute and charge back the EUR
+        // deposit with rows that don't specify a currency.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(20.0))
+            .with_currency(eur);
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .is_ok());
+
+        // Verify that the funds were held in EUR and that the USD balance was
+        // left untouched.
+        let account = ledger.accounts.get(&client).unwrap();
+        let balance = account.balance(eur).unwrap();
+        assert_eq!(balance.available, 0.0);
+        assert_eq!(balance.held, 20.0);
+        assert_eq!(ledger.usd_balance(&client).available, 10.0);
+
+        let tx4 = Transaction::new(TransactionType::Chargeback, client.clone(), 2, None);
+        assert!(ledger.process_transaction(&tx4).is_ok());
+
+        let account = ledger.accounts.get(&client).unwrap();
+        assert_eq!(account.balance(eur).unwrap().total, 0.0);
+        assert_eq!(ledger.usd_balance(&client).total, 10.0);
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn should_exchange_funds_at_effective_rate() {
+        // Create a ledger with a EUR/USD rate table and declare a client id
+        // and currency to use.
+        let rates = RateTable::from_reader(
+            "base,quote,effective,rate,spread\n\
+             EUR,USD,2021-01-01,1.25,1\n\
+             EUR,USD,2021-02-01,1.5,1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_exchange_rates(rates);
+        let client = ClientId::Numeric(1);
+        let eur: Currency = "EUR".parse().unwrap();
+
+        // Deposit EUR and exchange some of it to USD on a date where the
+        // first rate is effective.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(100.0))
+            .with_currency(eur);
+        let tx2 = Transaction::new(TransactionType::Exchange, client.clone(), 2, Some(80.0))
+            .with_currency(eur)
+            .with_to_currency(Currency::default())
+            .with_date("2021-01-15".parse().unwrap());
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
+        // Verify that 80 EUR bought 100 USD, less the 1% spread which was
+        // credited to the house account.
+        let account = ledger.accounts.get(&client).unwrap();
+        assert_eq!(account.balance(eur).unwrap().total, 20.0);
+        assert_eq!(ledger.usd_balance(&client).total, 99.0);
+        assert_eq!(ledger.usd_balance(&ClientId::house()).total, 1.0);
         assert_eq!(
-            ledger.process_transaction(&tx).unwrap_err().to_string(),
-            WithdrawalError::NegativeWithdrawal.to_string()
+            ledger.generate_exchange_report().unwrap(),
+            "tx,client,from,to,amount,rate,converted,spread\n\
+             2,1,EUR,USD,80.0,1.25,99.0,1.0\n"
         );
+
+        // Exchanging more than the client holds should be skipped without
+        // changing any balances.
+        let tx3 = Transaction::new(TransactionType::Exchange, client.clone(), 3, Some(50.0))
+            .with_currency(eur)
+            .with_to_currency(Currency::default());
+        assert!(ledger.process_transaction(&tx3).is_ok());
+        assert_eq!(ledger.accounts[&client].balance(eur).unwrap().total, 20.0);
+
+        // Exchanges dated before any rate is effective should be rejected.
+        let tx4 = Transaction::new(TransactionType::Exchange, client.clone(), 4, Some(10.0))
+            .with_currency(eur)
+            .with_to_currency(Currency::default())
+            .with_date("2020-12-31".parse().unwrap());
+        assert!(matches!(
+            ledger.process_exchange(&tx4),
+            Err(ExchangeError::NoEffectiveRate(_, _, _))
+        ));
+
+        // Exchanges to a currency without any rate should be rejected.
+        let tx5 = Transaction::new(TransactionType::Exchange, client.clone(), 5, Some(10.0))
+            .with_currency(eur)
+            .with_to_currency("JPY".parse().unwrap());
+        assert!(matches!(
+            ledger.process_exchange(&tx5),
+            Err(ExchangeError::NoEffectiveRate(_, _, _))
+        ));
+
+        // Undated exchanges use the rate effective on the date of the last
+        // dated row, rather than the latest rate in the table, and are
+        // rejected if no row was dated yet.
+        let tx6 = Transaction::new(TransactionType::Exchange, client.clone(), 6, Some(10.0))
+            .with_currency(eur)
+            .with_to_currency(Currency::default());
+        assert!(ledger.process_transaction(&tx6).is_ok());
+        assert_eq!(ledger.exchange_records.last().unwrap().rate, 1.25);
+        assert!(matches!(
+            Ledger::default().process_exchange(&tx6),
+            Err(ExchangeError::DateRequired)
+        ));
     }
 
     #[test]
-    fn should_fail_to_withdraw_from_a_locked_account() {
+    fn should_post_balanced_journal_entries() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
+
+        // Deposit twice, withdraw, then dispute and charge back the second
+        // deposit.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(100.0));
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 2, Some(40.0));
+        let tx3 = Transaction::new(TransactionType::Withdrawal, client.clone(), 3, Some(30.0));
+        let tx4 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        let tx5 = Transaction::new(TransactionType::Chargeback, client.clone(), 2, None);
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3, tx4, tx5]))
+            .is_ok());
 
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+        // Verify that the client's balance was derived from the journal.
+        assert_eq!(
+            ledger.journal.credit_balance(
+                &LedgerAccount::ClientLiability(client.clone()),
+                Currency::default()
+            ),
+            ledger.usd_balance(&client).available
+        );
+        assert_eq!(ledger.usd_balance(&client).total, 70.0);
 
-        // Now lock the account and attempt to make a withdrawal and verify
-        // that the transaction fails with the expected error.
-        ledger.lock_account(client);
-        let tx2 = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 2,
-            amount: Some(1.0),
-        };
+        // Verify that the journal is balanced and that the trial balance
+        // nets to zero.
+        assert!(ledger.verify().is_ok());
         assert_eq!(
-            ledger.process_transaction(&tx2).unwrap_err().to_string(),
-            WithdrawalError::AccountLocked.to_string()
+            ledger.generate_trial_balance().unwrap(),
+            "account,currency,debit,credit\n\
+             client_liability:1,USD,0.0,70.0\n\
+             suspense:1,USD,0.0,0.0\n\
+             chargeback_loss,USD,0.0,0.0\n\
+             settlement,USD,70.0,0.0\n"
         );
+
+        // Every entry is posted against the tx it was caused by.
+        let report = ledger.generate_journal_report().unwrap();
+        assert!(report.starts_with("tx,currency,debit,credit,amount\n"));
+        assert!(report.contains("2,USD,chargeback_loss,settlement,40.0\n"));
+        assert!(report.contains("2,USD,suspense:1,chargeback_loss,40.0\n"));
     }
 
     #[test]
-    fn should_fail_to_withdraw_from_an_account_with_insufficient_funds() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
+    fn should_enforce_withdrawal_limits_per_tier() {
+        // Create a ledger whose gold tier may withdraw at most 50 at a time
+        // and 80 per day, and declare a gold client to use.
+        let limits = WithdrawalLimits::from_reader(
+            "tier,max_single,max_count,max_sum,window_rows,window_days\n\
+             default,10,,,,\n\
+             gold,50,,80,,1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_withdrawal_limits(limits);
+        let client = ClientId::Numeric(1);
+        ledger.account_configs.insert(
+            client.clone(),
+            AccountConfig {
+                client: client.clone(),
+                credit_limit: 0.0,
+                tier: Some(String::from("gold")),
+            },
+        );
 
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+        // Deposit funds and withdraw within the limits.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(500.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(50.0))
+            .with_date("2021-01-01".parse().unwrap());
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 450.0);
 
-        // Now attempt to withdraw more money than we just deposited and verify
-        // the transaction succeeds, but doesn't complete the withdrawal.
-        let tx2 = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 2,
-            amount: Some(20.0),
-        };
-        assert!(ledger.process_transaction(&tx2).is_ok());
+        // A single withdrawal above the limit should fail.
+        let tx3 = Transaction::new(TransactionType::Withdrawal, client.clone(), 3, Some(60.0))
+            .with_date("2021-01-01".parse().unwrap());
+        assert_eq!(
+            ledger.process_withdrawal(&tx3).unwrap_err().to_string(),
+            WithdrawalError::LimitExceeded(LimitKind::MaxSingle, 50.0, 60.0).to_string()
+        );
 
-        // Now verify the state of the account the withdrawals failed on due to
-        // insufficient funds.
+        // Withdrawals beyond the daily sum should fail until the next day,
+        // and are skipped without stopping processing.
+        let tx4 = Transaction::new(TransactionType::Withdrawal, client.clone(), 4, Some(40.0))
+            .with_date("2021-01-01".parse().unwrap());
         assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 10.0,
-                held: 0.0,
-                total: 10.0,
-                locked: false
-            })
+            ledger.process_withdrawal(&tx4).unwrap_err().to_string(),
+            WithdrawalError::LimitExceeded(LimitKind::MaxSum, 80.0, 90.0).to_string()
         );
+        let tx5 = Transaction::new(TransactionType::Withdrawal, client.clone(), 5, Some(40.0))
+            .with_date("2021-01-02".parse().unwrap());
+        assert!(ledger.process_transaction(&tx5).is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 410.0);
     }
 
     #[test]
-    fn should_fail_to_withdraw_from_an_unknown_account() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Attempt to withdraw money from an unknown account.
-        let tx1 = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 1,
-            amount: Some(20.0),
+    fn should_evaluate_risk_rules_before_applying_transactions() {
+        // Create a ledger which flags withdrawals right after large deposits,
+        // holds repeated disputes and rejects deposits after chargebacks.
+        let rules = RiskRules::default()
+            .with_rule(Box::new(LargeDepositWithdrawal {
+                min_deposit: 1000.0,
+                window_rows: 1,
+                action: RiskAction::Flag,
+            }))
+            .with_rule(Box::new(RepeatedDisputes {
+                max_disputes: 1,
+                action: RiskAction::Hold,
+            }))
+            .with_rule(Box::new(DepositAfterChargeback {
+                action: RiskAction::Reject,
+            }));
+        let mut ledger = Ledger::default().with_risk_rules(rules);
+        let client = ClientId::Numeric(1);
+
+        // A large deposit immediately followed by a withdrawal is flagged but
+        // still applied.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(1000.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(900.0));
+        let tx3 = Transaction::new(TransactionType::Deposit, client.clone(), 3, Some(10.0));
+        let tx4 = Transaction::new(TransactionType::Deposit, client.clone(), 4, Some(20.0));
+        assert!(ledger
+            .process_transactions(Vec::from([tx1, tx2, tx3, tx4]))
+            .is_ok());
+        assert_eq!(ledger.usd_balance(&client).total, 130.0);
+
+        // The second dispute from the client is held until it's released.
+        let tx5 = Transaction::new(TransactionType::Dispute, client.clone(), 3, None);
+        let tx6 = Transaction::new(TransactionType::Dispute, client.clone(), 4, None);
+        assert!(ledger.process_transactions(Vec::from([tx5, tx6])).is_ok());
+        assert!(ledger.is_disputed(3));
+        assert!(!ledger.is_disputed(4));
+        let other = TransactionKey {
+            source: Some(Arc::from("other")),
+            tx: 4,
         };
+        assert!(!ledger.release_held(&other).unwrap());
+        assert!(ledger.release_held(&key(4)).unwrap());
+        assert!(ledger.is_disputed(4));
+        assert!(!ledger.release_held(&key(4)).unwrap());
+
+        // Deposits after a chargeback are rejected.
+        let tx7 = Transaction::new(TransactionType::Chargeback, client.clone(), 3, None);
+        let tx8 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(2),
+            8,
+            Some(50.0),
+        );
+        let tx9 = Transaction::new(TransactionType::Deposit, client.clone(), 9, Some(50.0));
+        assert!(ledger
+            .process_transactions(Vec::from([tx7, tx8, tx9]))
+            .is_ok());
+        assert!(ledger.transactions.contains_key(&key(8)));
+        assert!(!ledger.transactions.contains_key(&key(9)));
+
         assert_eq!(
-            ledger.process_transaction(&tx1).unwrap_err().to_string(),
-            WithdrawalError::NoSuchAccount(client).to_string()
+            ledger.generate_alert_report().unwrap(),
+            "row,tx,client,type,rule,action\n\
+             2,2,1,withdrawal,large_deposit_withdrawal,flag\n\
+             6,4,1,dispute,repeated_disputes,hold\n\
+             10,9,1,deposit,deposit_after_chargeback,reject\n"
         );
     }
 
     #[test]
-    fn should_withdraw_from_account() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
-
-        for tx_id in 1..10 {
-            let tx = Transaction {
-                r#type: TransactionType::Withdrawal,
-                client,
-                tx: tx_id + 1, // Need to add 1 because we created tx 1 above
-                amount: Some(1.0),
-            };
-            assert!(ledger.process_transaction(&tx).is_ok());
-        }
+    fn should_quarantine_blocklisted_clients() {
+        // Create a ledger which blocks client 2.
+        let blocklist =
+            Blocklist::from_reader("client,name,reason\n2,,sanctions list\n".as_bytes()).unwrap();
+        let mut ledger = Ledger::default().with_blocklist(blocklist);
+
+        // Deposit funds for both clients.
+        let tx1 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(1),
+            1,
+            Some(10.0),
+        );
+        let tx2 = Transaction::new(
+            TransactionType::Deposit,
+            ClientId::Numeric(2),
+            2,
+            Some(20.0),
+        );
+        assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
-        // Now verify the state of the account the withdrawals were made from.
+        // Verify that no account was created for the blocklisted client and
+        // that its deposit was quarantined with the reason.
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 10.0);
+        assert!(!ledger.accounts.contains_key(&ClientId::Numeric(2)));
+        assert!(!ledger.transactions.contains_key(&key(2)));
         assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
-                locked: false
-            })
+            ledger.generate_quarantine_report().unwrap(),
+            "row,type,client,tx,amount,currency,reason\n\
+             2,deposit,2,2,20.0,USD,client 2 is blocklisted: sanctions list\n"
         );
     }
 
     #[test]
-    fn should_fail_to_dispute_a_dispute() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
+    fn should_review_accounts_below_chargeback_threshold() {
+        // Create a ledger which only locks accounts on their second
+        // chargeback.
+        let policy = ChargebackPolicy {
+            lock_count: Some(2),
+            lock_ratio: None,
+            min_deposits: 0,
         };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+        let mut ledger = Ledger::default().with_chargeback_policy(policy);
+        let client = ClientId::Numeric(1);
+
+        // Deposit four times, then dispute and charge back the first deposit.
+        let mut transactions = Vec::new();
+        for tx in 1..=4 {
+            transactions.push(Transaction::new(
+                TransactionType::Deposit,
+                client.clone(),
+                tx,
+                Some(10.0),
+            ));
+        }
+        for r#type in [TransactionType::Dispute, TransactionType::Chargeback] {
+            transactions.push(Transaction::new(r#type, client.clone(), 1, None));
+        }
+        assert!(ledger.process_transactions(transactions).is_ok());
 
-        // Manually insert a dispute transaction.
-        //
-        // Note: We must manually insert this transaction since we _shouldn't_
-        // otherwise be able to end up with an existing transaction that has no
-        // amount.
-        ledger.transactions.insert(
-            2,
-            Transaction {
-                r#type: TransactionType::Dispute,
-                client,
-                tx: 1,
-                amount: None,
-            },
-        );
+        // Verify that the account was placed under review but not locked.
+        let account = ledger.accounts.get(&client).unwrap();
+        assert!(account.review);
+        assert!(!account.locked);
+        assert_eq!(ledger.usd_balance(&client).total, 30.0);
+
+        // A second chargeback locks the account.
+        let tx5 = Transaction::new(TransactionType::Dispute, client.clone(), 2, None);
+        let tx6 = Transaction::new(TransactionType::Chargeback, client.clone(), 2, None);
+        assert!(ledger.process_transactions(Vec::from([tx5, tx6])).is_ok());
+        assert!(ledger.accounts.get(&client).unwrap().locked);
 
-        // Now attempt to dispute the dispute (tx with no amount) and verify
-        // that the transaction fails.
-        let tx3 = Transaction {
-            r#type: TransactionType::Dispute,
-            client,
-            tx: 2,
-            amount: None,
-        };
         assert_eq!(
-            ledger.process_transaction(&tx3).unwrap_err().to_string(),
-            DisputeError::AmountRequired.to_string()
+            ledger.generate_monitoring_report().unwrap(),
+            "client,deposits,disputes,chargebacks,dispute_ratio,chargeback_ratio,review,locked\n\
+             1,4,2,2,0.5,0.5,true,true\n"
         );
     }
 
     #[test]
-    fn should_dispute_a_deposit() {
-        // Create a ledger and declare a client id to use.
-        let mut ledger = Ledger::default();
-        let client = 1u16;
+    fn should_only_open_accounts_for_registered_clients() {
+        // Create a strict ledger whose registry knows clients 1 and 2, and
+        // which blocks clients by name.
+        let registry = ClientRegistry::from_reader(
+            "client,name,tier,status,opened,credit_limit\n\
+             1,Acme Ltd,gold,active,2024-01-15,50\n\
+             2,Jane Doe,,active,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let blocklist =
+            Blocklist::from_reader("client,name,reason\n,jane doe,sanctions list\n".as_bytes())
+                .unwrap();
+        let mut ledger = Ledger::default()
+            .with_client_registry(registry, true)
+            .with_blocklist(blocklist);
+
+        // Deposit funds for a registered, a blocklisted and an unregistered
+        // client.
+        let mut transactions = Vec::new();
+        for client in 1..=3 {
+            transactions.push(Transaction::new(
+                TransactionType::Deposit,
+                ClientId::Numeric(client),
+                client as u32,
+                Some(10.0),
+            ));
+        }
+        assert!(ledger.process_transactions(transactions).is_ok());
 
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+        // Only the registered client's account was opened, with the credit
+        // line from the registry.
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 10.0);
+        assert_eq!(
+            ledger
+                .accounts
+                .get(&ClientId::Numeric(1))
+                .unwrap()
+                .credit_limit,
+            50.0
+        );
+        assert!(!ledger.accounts.contains_key(&ClientId::Numeric(2)));
+        assert!(!ledger.accounts.contains_key(&ClientId::Numeric(3)));
+        assert!(!ledger.transactions.contains_key(&key(3)));
+        assert_eq!(
+            ledger.open_account(&ClientId::Numeric(3)),
+            Err(AccountError::NoSuchAccount(ClientId::Numeric(3)))
+        );
 
-        // Now dispute that deposit and verify that we complete successfully
-        // and that the ledger shows the transaction is disputed.
-        let tx2 = Transaction {
-            r#type: TransactionType::Dispute,
-            client,
-            tx: 1,
-            amount: None,
-        };
-        assert!(ledger.process_transaction(&tx2).is_ok());
-        assert!(ledger.disputed_transactions.contains(&1));
+        // The blocklisted client was screened by their registered name.
+        assert_eq!(
+            ledger.generate_quarantine_report().unwrap(),
+            "row,type,client,tx,amount,currency,reason\n\
+             2,deposit,2,2,10.0,USD,name jane doe is blocklisted: sanctions list\n"
+        );
+
+        // The registry's tier and credit line take precedence over the
+        // account configuration, which only fills the ones it doesn't set.
+        assert!(ledger
+            .load_account_config_from_reader(
+                "client,credit_limit,tier\n\
+                 1,5,silver\n\
+                 2,5,silver\n"
+                    .as_bytes(),
+            )
+            .is_ok());
+        let config = &ledger.account_configs[&ClientId::Numeric(1)];
+        assert_eq!(config.tier.as_deref(), Some("gold"));
+        assert_eq!(config.credit_limit, 50.0);
+        let config = &ledger.account_configs[&ClientId::Numeric(2)];
+        assert_eq!(config.tier.as_deref(), Some("silver"));
+        assert_eq!(config.credit_limit, 5.0);
+
+        // The statement includes the client's registration.
+        assert!(ledger.generate_account_statements().unwrap().contains(
+            "1,USD,10.0,0.0,10.0,false,false,false,0.0,50.0,0.0,Acme Ltd,gold,active,2024-01-15\n"
+        ));
     }
 
     #[test]
-    fn should_dispute_a_withdrawal() {
-        // Create a ledger and declare a client id to use.
+    fn should_process_opaque_client_ids() {
+        // Create a ledger and declare a partner-assigned client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::from("3f2b8c1e-9d4a-4e7b-8a6f-2c1d0e9b7a53");
 
-        // Process a couple transactions and verify that they complete
-        // successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        let tx2 = Transaction {
-            r#type: TransactionType::Withdrawal,
-            client,
-            tx: 2,
-            amount: Some(5.0),
-        };
+        // Deposit and withdraw funds for the client.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(4.0));
         assert!(ledger.process_transactions(Vec::from([tx1, tx2])).is_ok());
 
-        // Now dispute the withdrawal and verify that we complete successfully
-        // and that the ledger shows the transaction is disputed.
-        let tx3 = Transaction {
-            r#type: TransactionType::Dispute,
-            client,
-            tx: 2,
-            amount: None,
-        };
-        assert!(ledger.process_transaction(&tx3).is_ok());
-        assert!(ledger.disputed_transactions.contains(&2));
+        // Verify that the id is reported as it was read.
+        assert!(ledger.generate_account_statements().unwrap().contains(
+            "3f2b8c1e-9d4a-4e7b-8a6f-2c1d0e9b7a53,USD,6.0,0.0,6.0,false,false,false,0.0,0.0,0.0\n"
+        ));
+
+        // Errors report the id too.
+        let tx3 = Transaction::new(
+            TransactionType::Withdrawal,
+            ClientId::from("unknown-client"),
+            3,
+            Some(1.0),
+        );
+        assert_eq!(
+            ledger.process_withdrawal(&tx3).unwrap_err().to_string(),
+            WithdrawalError::NoSuchAccount(ClientId::from("unknown-client")).to_string()
+        );
     }
 
     #[test]
-    fn should_resolve_a_disputed_transaction() {
+    fn should_namespace_transactions_by_source() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Deposit funds into a new account, dispute the deposit, and then
-        // resolve the dispute and assert the
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        let tx2 = Transaction {
-            r#type: TransactionType::Dispute,
-            client,
-            tx: 1,
-            amount: None,
-        };
-        let tx3 = Transaction {
-            r#type: TransactionType::Resolve,
-            client,
-            tx: 1,
-            amount: None,
-        };
+        let client = ClientId::Numeric(1);
+        let partner_a: Arc<str> = Arc::from("partner_a");
+        let partner_b: Arc<str> = Arc::from("partner_b");
+
+        // Deposit funds from two partners whose tx ids collide, then dispute
+        // the deposit of the second partner.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0))
+            .with_source(partner_a.clone());
+        let tx2 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(4.0))
+            .with_source(partner_b.clone());
+        let tx3 = Transaction::new(TransactionType::Dispute, client.clone(), 1, None)
+            .with_source(partner_b.clone());
         assert!(ledger
-            .process_transactions(Vec::from([tx1, tx2, tx3]))
+            .process_transactions(Vec::from([tx1.clone(), tx2, tx3]))
             .is_ok());
 
-        // Now assert that the account has the expected balance and that the
-        // transaction is no longer disputed.
+        // Verify that only the second partner's deposit is held.
+        let balance = ledger.usd_balance(&client);
+        assert_eq!(balance.available, 10.0);
+        assert_eq!(balance.held, 4.0);
+
+        // A duplicate within the same source is still rejected, and reports
+        // the source.
         assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 10.0,
-                held: 0.0,
-                total: 10.0,
-                locked: false
-            })
+            ledger.process_deposit(&tx1).unwrap_err().to_string(),
+            "failed to deposit funds: duplicate transaction id 1 from source partner_a detected"
         );
     }
 
     #[test]
-    fn should_fail_to_resolve_a_transaction_with_no_amount() {
+    fn should_skip_transactions_already_applied() {
         // Create a ledger and declare a client id to use.
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        let client = ClientId::Numeric(1);
+        let source: Arc<str> = Arc::from("partner_a");
+
+        // Deposit and withdraw funds, then ingest the same rows again as if
+        // the partner had re-sent their file.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(10.0))
+            .with_source(source.clone());
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(4.0))
+            .with_source(source.clone());
+        let transactions = Vec::from([tx1.clone(), tx2]);
+        assert!(ledger.process_transactions(transactions.clone()).is_ok());
+        assert!(ledger.process_transactions(transactions).is_ok());
+
+        // Verify that the rows were only applied once, and that the
+        // duplicates were recorded.
+        assert_eq!(ledger.usd_balance(&client).total, 6.0);
+        assert_eq!(
+            ledger.generate_duplicate_report().unwrap(),
+            "row,source,tx,type,client,status\n\
+             3,partner_a,1,deposit,1,already_applied\n\
+             4,partner_a,2,withdrawal,1,already_applied\n"
+        );
 
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
+        // A row reusing an applied tx id with different content is an error.
+        let conflict = Transaction {
+            amount: Some(20.0),
+            ..tx1
         };
-        assert!(ledger.process_transaction(&tx1).is_ok());
+        assert!(ledger.process_transaction(&conflict).is_err());
+        assert_eq!(ledger.usd_balance(&client).total, 6.0);
+    }
 
-        // Manually insert a dispute transaction and add it to the
-        // disputed_transactions set.
-        //
-        // Note: We must manually insert this transaction since we _shouldn't_
-        // otherwise be able to end up with an existing transaction that has no
-        // amount.
-        ledger.transactions.insert(
-            2,
-            Transaction {
-                r#type: TransactionType::Dispute,
-                client,
-                tx: 1,
-                amount: None,
-            },
+    #[test]
+    fn should_skip_dispute_rows_already_applied() {
+        let mut ledger = Ledger::default().with_chargeback_policy(ChargebackPolicy {
+            lock_count: None,
+            ..Default::default()
+        });
+
+        // The partner re-sends their chargeback of part of a deposit, and
+        // then charges back the rest with a different amount.
+        let data = "type,client,tx,amount,source\n\
+                    deposit,1,1,10.0,partner_a\n\
+                    dispute,1,1,,partner_a\n\
+                    chargeback,1,1,4.0,partner_a\n\
+                    chargeback,1,1,4.0,partner_a\n\
+                    chargeback,1,1,6.0,partner_a\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        // The re-sent chargeback is only applied once.
+        let balance = ledger.usd_balance(&ClientId::Numeric(1));
+        assert_eq!((balance.held, balance.total), (0.0, 0.0));
+        assert_eq!(
+            ledger.generate_duplicate_report().unwrap(),
+            "row,source,tx,type,client,status\n\
+             4,partner_a,1,chargeback,1,already_applied\n"
         );
-        ledger.disputed_transactions.insert(2);
+    }
 
-        // Now attempt to dispute the dispute (tx with no amount) and verify
-        // that the transaction fails.
-        let tx3 = Transaction {
-            r#type: TransactionType::Resolve,
-            client,
-            tx: 2,
-            amount: None,
-        };
+    #[test]
+    fn should_skip_accrue_rows_already_applied() {
+        let schedule =
+            InterestSchedule::from_reader("threshold,rate\n0,10\n".as_bytes(), DayCount::Actual360)
+                .unwrap();
+        let mut ledger = Ledger::default().with_interest_schedule(schedule);
+
+        // The partner re-sends a file containing an accrue row.
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,1000.0\n\
+                    accrue,0,2,36\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        // The interest is only posted once, and the accrue can't be
+        // disputed.
+        let dispute = Transaction::new(TransactionType::Dispute, ClientId::Numeric(1), 2, None);
+        assert!(ledger.process_transaction(&dispute).is_ok());
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).available, 1010.0);
+        assert_eq!(ledger.accrual_records.len(), 1);
         assert_eq!(
-            ledger.process_transaction(&tx3).unwrap_err().to_string(),
-            ResolveError::AmountRequired.to_string()
+            ledger.generate_duplicate_report().unwrap(),
+            "row,source,tx,type,client,status\n\
+             3,,1,deposit,1,already_applied\n\
+             4,,2,accrue,0,already_applied\n"
         );
+        assert_eq!(ledger.warnings[0].warning, PartnerWarning::UnknownTx);
     }
 
     #[test]
-    fn should_chargeback_a_disputed_transaction() {
-        // Create a ledger and declare a client id to use.
+    fn should_apply_disputes_again_once_resolved() {
+        // A deposit is disputed and resolved, then disputed again and
+        // charged back, in full and in part.
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,1,\n\
+                    resolve,1,1,\n\
+                    dispute,1,1,\n\
+                    chargeback,1,1,\n\
+                    deposit,2,2,10.0\n\
+                    dispute,2,2,4.0\n\
+                    resolve,2,2,4.0\n\
+                    dispute,2,2,4.0\n\
+                    chargeback,2,2,4.0\n";
         let mut ledger = Ledger::default();
-        let client = 1u16;
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        // The second dispute of each cycle is applied, not reported.
+        let account = &ledger.accounts[&ClientId::Numeric(1)];
+        assert!(account.locked);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 0.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(2)).total, 6.0);
+        assert!(ledger.duplicates.is_empty());
+        assert!(ledger.warnings.is_empty());
+    }
 
-        // Create two deposits, dispute the largest deposit, and then issue a
-        // chargeback and verify that the result is successful.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        let tx2 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 2,
-            amount: Some(1000.0),
-        };
-        let tx3 = Transaction {
-            r#type: TransactionType::Dispute,
-            client,
-            tx: 2,
-            amount: None,
-        };
-        let tx4 = Transaction {
-            r#type: TransactionType::Chargeback,
-            client,
-            tx: 2,
-            amount: None,
+    #[test]
+    fn should_fail_on_colliding_statement_references() {
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+        let statement: Arc<str> = Arc::from("STMT-1");
+
+        // Two bank references hash to the same tx id, but an entry for the
+        // second isn't mistaken for a re-import of the first.
+        let entry = |reference: &str| {
+            Transaction::new(
+                TransactionType::Deposit,
+                client.clone(),
+                Transaction::tx_for_reference(reference),
+                Some(10.0),
+            )
+            .with_source(statement.clone())
+            .with_memo(String::from(reference))
         };
+        assert!(ledger.process_transaction(&entry("REF-462789")).is_ok());
+        assert!(ledger.process_transaction(&entry("REF-462789")).is_ok());
+        assert!(ledger.process_transaction(&entry("REF-679192")).is_err());
+        assert_eq!(ledger.duplicates.len(), 1);
+        assert_eq!(ledger.usd_balance(&client).total, 10.0);
+    }
+
+    #[test]
+    fn should_evaluate_rejected_transactions_again_when_resent() {
+        let mut ledger = Ledger::default();
+        let client = ClientId::Numeric(1);
+
+        // A withdrawal is rejected for insufficient funds, and re-sent once
+        // the client has deposited enough to cover it.
+        let tx1 = Transaction::new(TransactionType::Deposit, client.clone(), 1, Some(5.0));
+        let tx2 = Transaction::new(TransactionType::Withdrawal, client.clone(), 2, Some(8.0));
+        let tx3 = Transaction::new(TransactionType::Deposit, client.clone(), 3, Some(5.0));
         assert!(ledger
-            .process_transactions(Vec::from([tx1, tx2, tx3, tx4]))
+            .process_transactions(Vec::from([tx1, tx2.clone(), tx3, tx2]))
             .is_ok());
 
-        // Now verify that the account shows the correct balance.
-        assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 10.0,
-                held: 0.0,
-                total: 10.0,
-                locked: true
-            })
-        );
+        // The re-sent withdrawal is applied rather than reported.
+        assert_eq!(ledger.usd_balance(&client).total, 2.0);
+        assert!(ledger.duplicates.is_empty());
     }
 
     #[test]
-    fn should_fail_to_chargeback_a_transaction_with_no_amount() {
-        // Create a ledger and declare a client id to use.
+    fn should_roll_back_failed_batches() {
         let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Process a deposit and verify that it completes successfully.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(1.0),
-        };
-        assert!(ledger.process_transaction(&tx1).is_ok());
 
-        // Manually insert a dispute transaction and add it to the
-        // disputed_transactions set.
-        //
-        // Note: We must manually insert this transaction since we _shouldn't_
-        // otherwise be able to end up with an existing transaction that has no
-        // amount.
-        ledger.transactions.insert(
-            2,
-            Transaction {
-                r#type: TransactionType::Dispute,
-                client,
-                tx: 1,
-                amount: None,
-            },
-        );
-        ledger.disputed_transactions.insert(2);
+        // The first batch overdraws client 2, so it is rolled back as a
+        // whole, while the second batch and the unbatched rows are applied.
+        let data = "type,client,tx,amount,batch\n\
+                    deposit,1,1,10.0,\n\
+                    deposit,2,2,5.0,a\n\
+                    withdrawal,2,3,8.0,a\n\
+                    deposit,1,4,1.0,a\n\
+                    deposit,2,5,3.0,b\n\
+                    withdrawal,1,6,2.0,\n";
+        assert!(ledger
+            .process_reader(data.as_bytes(), Some(Arc::from("settlement")))
+            .is_ok());
 
-        // Now attempt to chargeback the dispute (tx with no amount) and verify
-        // that the transaction fails.
-        let tx3 = Transaction {
-            r#type: TransactionType::Chargeback,
-            client,
-            tx: 2,
-            amount: None,
-        };
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).total, 8.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(2)).total, 3.0);
+        assert!(!ledger.in_batch);
         assert_eq!(
-            ledger.process_transaction(&tx3).unwrap_err().to_string(),
-            ChargebackError::AmountRequired.to_string()
+            ledger.generate_batch_report().unwrap(),
+            "source,batch,first_row,last_row,status,reason\n\
+             settlement,a,2,4,rolled_back,insufficient funds to complete this transaction wanted=8 had=5 credit_limit=0\n\
+             settlement,b,5,5,committed,\n"
         );
+
+        // In a batch, a dispute rejected for insufficient funds fails the
+        // batch rather than being skipped.
+        let mut ledger =
+            Ledger::default().with_negative_balance_policy(NegativeBalancePolicy::Reject);
+        let data = "type,client,tx,amount,batch\n\
+                    deposit,1,1,10.0,a\n\
+                    withdrawal,1,2,10.0,a\n\
+                    dispute,1,1,,a\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert!(ledger.accounts.is_empty());
+        assert!(ledger.warnings.is_empty());
+
+        // Applying a file atomically rolls back every row, including those
+        // before the failure.
+        let mut ledger = Ledger::default().with_atomic_files(true);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    withdrawal,1,2,20.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert!(ledger.accounts.is_empty());
+        assert!(ledger.transactions.is_empty());
+        assert_eq!(ledger.batches[0].status, "rolled_back");
+        assert_eq!(ledger.batches[0].last_row, 2);
+
+        // A row that can't be decoded rolls back the file and stops
+        // processing.
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,x,1.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_err());
+        assert!(ledger.accounts.is_empty());
+        assert_eq!(ledger.batches.len(), 2);
     }
 
     #[test]
-    fn should_round_values() {
-        // Create a ledger and declare a client id to use.
+    fn should_undo_partially_applied_batches() {
         let mut ledger = Ledger::default();
-        let client = 1u16;
-
-        // Deposit a value with more than 4 decimal points and verify that the
-        // stored value is rounded to 4.
-        let tx = Transaction {
-            r#type: TransactionType::Deposit,
-            client,
-            tx: 1,
-            amount: Some(8.675309),
-        };
-        assert!(ledger.process_transaction(&tx).is_ok());
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,2,2,5.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        let accounts = ledger.accounts.clone();
+        let transactions = ledger.transactions.clone();
+        let states = ledger.transaction_states.clone();
+        let entries = ledger.journal.entries().to_vec();
+        let trial_balance = ledger.journal.trial_balance();
+
+        // The batch disputes, withdraws, deposits and opens an account before
+        // client 2 overdraws, so every applied row is undone.
+        let data = "type,client,tx,amount,batch\n\
+                    dispute,1,1,4.0,a\n\
+                    withdrawal,2,3,3.0,a\n\
+                    deposit,1,4,2.0,a\n\
+                    deposit,3,5,7.0,a\n\
+                    withdrawal,2,6,50.0,a\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert_eq!(ledger.batches[0].status, "rolled_back");
+
+        // The balances, journal and transaction map are as they were.
+        assert_eq!(ledger.accounts, accounts);
+        assert_eq!(ledger.transactions, transactions);
+        assert_eq!(ledger.transaction_states, states);
+        assert_eq!(ledger.journal.entries(), entries.as_slice());
+        assert_eq!(ledger.journal.trial_balance(), trial_balance);
+        assert!(ledger.applied_references.is_empty());
+
+        // The rolled back rows can be applied again.
+        let data = "type,client,tx,amount\n\
+                    dispute,1,1,4.0\n\
+                    deposit,3,5,7.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).held, 4.0);
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(3)).total, 7.0);
+    }
 
-        // Now verify that the account shows the correct balance.
+    #[test]
+    fn should_retry_disputes_that_arrive_before_their_transaction() {
+        let mut ledger = Ledger::default().with_reorder_window(2);
+
+        // The dispute of tx 1 arrives before the deposit and is retried once
+        // it does, while the dispute of tx 9 never finds its deposit.
+        let data = "type,client,tx,amount\n\
+                    dispute,1,1,\n\
+                    dispute,1,9,\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,2,5.0\n\
+                    deposit,1,3,1.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        assert!(ledger.is_disputed(1));
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).held, 10.0);
         assert_eq!(
-            ledger.accounts.get(&client),
-            Some(&Account {
-                client,
-                available: 8.6753,
-                held: 0.0,
-                total: 8.6753,
-                locked: false
-            })
+            ledger.generate_orphan_report().unwrap(),
+            "row,source,tx,type,client,expired_row\n\
+             2,,9,dispute,1,5\n"
         );
+
+        // Orphans still parked once the input ends are expired too.
+        let data = "type,client,tx,amount\nresolve,1,7,\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+        ledger.expire_orphans();
+        assert_eq!(ledger.expired_orphans.len(), 2);
+        assert!(ledger.orphans.is_empty());
     }
 
     #[test]
-    fn should_generate_statement_report() {
-        // Create a new ledger.
+    fn should_continue_past_invalid_dispute_amounts() {
         let mut ledger = Ledger::default();
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,1,4.0\n\
+                    resolve,1,1,5.0\n\
+                    chargeback,1,1,6.0\n\
+                    dispute,1,1,-1.0\n\
+                    resolve,1,1,-1.0\n\
+                    chargeback,1,1,-1.0\n\
+                    deposit,1,2,5.0\n\
+                    resolve,1,1,\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        // The rows with invalid amounts are reported, and the rows after
+        // them are still applied.
+        let balance = ledger.usd_balance(&ClientId::Numeric(1));
+        assert_eq!((balance.held, balance.total), (0.0, 15.0));
+        assert_eq!(
+            ledger.generate_warning_report().unwrap(),
+            "row,source,tx,type,client,warning\n\
+             3,,1,resolve,1,exceeds_disputed\n\
+             4,,1,chargeback,1,exceeds_disputed\n\
+             5,,1,dispute,1,negative_amount\n\
+             6,,1,resolve,1,negative_amount\n\
+             7,,1,chargeback,1,negative_amount\n"
+        );
+    }
 
-        // Deposit some values into the ledger.
-        let tx1 = Transaction {
-            r#type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(10.0),
-        };
-        let tx2 = Transaction {
-            r#type: TransactionType::Deposit,
-            client: 2,
-            tx: 2,
-            amount: Some(20.0),
-        };
-        let tx3 = Transaction {
-            r#type: TransactionType::Deposit,
-            client: 3,
-            tx: 3,
-            amount: Some(30.0),
-        };
-        assert!(ledger
-            .process_transactions(Vec::from([tx1, tx2, tx3]))
-            .is_ok());
-
-        // Now verify that the account shows the correct balance.
-        let result = ledger.generate_account_statements();
-        assert!(result.is_ok());
+    #[test]
+    fn should_report_ignored_dispute_rows() {
+        let mut ledger = Ledger::default();
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,2,\n\
+                    resolve,1,1,\n\
+                    dispute,1,1,\n\
+                    dispute,1,1,\n\
+                    chargeback,1,3,\n\
+                    dispute,1,1,2.0\n\
+                    deposit,1,4,5.0\n\
+                    dispute,1,4,6.0\n";
+        assert!(ledger.process_reader(data.as_bytes(), None).is_ok());
+
+        // Only the first dispute of tx 1 was applied, and the second is a
+        // re-send rather than a warning.
+        assert_eq!(ledger.usd_balance(&ClientId::Numeric(1)).held, 10.0);
+        assert_eq!(
+            ledger.generate_warning_report().unwrap(),
+            "row,source,tx,type,client,warning\n\
+             2,,2,dispute,1,unknown_tx\n\
+             3,,1,resolve,1,not_disputed\n\
+             6,,3,chargeback,1,unknown_tx\n\
+             7,,1,dispute,1,already_disputed\n\
+             9,,4,dispute,1,exceeds_disputable\n"
+        );
+        assert_eq!(
+            ledger.generate_duplicate_report().unwrap(),
+            "row,source,tx,type,client,status\n\
+             5,,1,dispute,1,already_applied\n"
+        );
+        assert_eq!(
+            ledger.warning_counts(),
+            BTreeMap::from([
+                (PartnerWarning::UnknownTx, 2),
+                (PartnerWarning::NotDisputed, 1),
+                (PartnerWarning::AlreadyDisputed, 1),
+                (PartnerWarning::ExceedsDisputable, 1),
+                (PartnerWarning::ExceedsDisputed, 0),
+                (PartnerWarning::NegativeAmount, 0),
+                (PartnerWarning::InsufficientFunds, 0)
+            ])
+        );
+    }
 
-        // We don't guarantee a sort order for the output, so simply assert
-        // that the lines we expect to see are present in the output.
-        let output = result.unwrap();
-        assert!(output.starts_with("client,available,held,total,locked\n"));
-        let expected_lines = [
-            "1,10.0,0.0,10.0,false\n",
-            "2,20.0,0.0,20.0,false\n",
-            "3,30.0,0.0,30.0,false\n",
-        ];
-        for line in expected_lines {
-            assert!(output.contains(line));
+    #[test]
+    fn should_reconcile_mt940_balances() {
+        // Fees aren't charged on statement lines, so they don't throw off
+        // the reconciliation.
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\n\
+             deposit,,,1,,\n\
+             withdrawal,,0.5,,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut ledger = Ledger::default().with_fee_schedule(schedule, ClientId::house());
+        let accounts =
+            BankAccounts::from_reader("account,client\nNL91ABNA0417164300,1\n".as_bytes()).unwrap();
+        let statements = crate::mt940::parse(
+            ":20:STMT1\n\
+             :25:NL91ABNA0417164300\n\
+             :28C:1/1\n\
+             :60F:C240301EUR0,\n\
+             :61:240301C100,NTRFNONREF\n\
+             :61:240302D30,NTRFNONREF\n\
+             :62F:C240302EUR70,\n\
+             :20:STMT2\n\
+             :25:NL91ABNA0417164300\n\
+             :28C:2/1\n\
+             :60F:C240302EUR70,\n\
+             :61:240303C5,NTRFNONREF\n\
+             :62F:C240303EUR80,\n",
+        )
+        .unwrap();
+        // The first statement is imported twice, which is a no-op that
+        // matches its opening balance too.
+        for statement in [&statements[0], &statements[0], &statements[1]] {
+            assert!(ledger.process_mt940_statement(statement, &accounts).is_ok());
         }
+
+        // Only the closing balance of the second statement doesn't match.
+        let eur = Currency::from_str("EUR").unwrap();
+        let account = ledger.accounts.get(&ClientId::Numeric(1)).unwrap();
+        assert_eq!(account.balance(eur).unwrap().total, 75.0);
+        assert_eq!(
+            ledger.generate_reconciliation_report().unwrap(),
+            "statement,account,client,currency,balance,expected,actual\n\
+             STMT2,NL91ABNA0417164300,1,EUR,closing,80.0,75.0\n"
+        );
+        assert!(ledger.fee_records.is_empty());
     }
 }
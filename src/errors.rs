@@ -1,3 +1,8 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::engine::TransactionKey;
+use crate::limits::LimitKind;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
@@ -5,19 +10,30 @@ use std::string::FromUtf8Error;
 
 #[derive(Debug, PartialEq)]
 pub struct DuplicateTransactionError {
-    tx_id: u32,
+    key: TransactionKey,
 }
 
 impl DuplicateTransactionError {
-    pub fn new(tx_id: u32) -> Self {
-        Self { tx_id }
+    pub fn new(key: TransactionKey) -> Self {
+        Self { key }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HouseAccountError {
+    client: ClientId,
+}
+
+impl HouseAccountError {
+    pub fn new(client: ClientId) -> Self {
+        Self { client }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum AccountError {
-    AccountLocked(u16),
-    NoSuchAccount(u16),
+    AccountLocked(ClientId),
+    NoSuchAccount(ClientId),
 }
 
 #[derive(Debug, PartialEq)]
@@ -26,37 +42,102 @@ pub enum DepositError {
     AccountLocked,
     DuplicateTx(DuplicateTransactionError),
     NegativeDeposit,
+    FeeExceedsAmount(f64, f64),
+    ScreeningHit(String),
+    NoSuchAccount(ClientId),
 }
 
 #[derive(Debug)]
 pub enum WithdrawalError {
     AmountRequired,
     AccountLocked,
-    InsufficientFunds(f64, f64),
-    NoSuchAccount(u16),
+    InsufficientFunds(f64, f64, f64),
+    NoSuchAccount(ClientId),
     DuplicateTx(DuplicateTransactionError),
     NegativeWithdrawal,
+    LimitExceeded(LimitKind, f64, f64),
 }
 
 #[derive(Debug)]
 pub enum DisputeError {
     AccountLocked,
-    NoSuchAccount(u16),
+    NoSuchAccount(ClientId),
     AmountRequired,
+    InsufficientFunds(f64, f64),
 }
 
 #[derive(Debug)]
 pub enum ResolveError {
     AccountLocked,
-    NoSuchAccount(u16),
+    NoSuchAccount(ClientId),
     AmountRequired,
 }
 
 #[derive(Debug)]
 pub enum ChargebackError {
     AccountLocked,
-    NoSuchAccount(u16),
+    NoSuchAccount(ClientId),
+    AmountRequired,
+}
+
+#[derive(Debug)]
+pub enum CreditLimitError {
+    AmountRequired,
+    NegativeLimit,
+    BelowCreditUsed(f64, f64),
+}
+
+#[derive(Debug)]
+pub enum AccrualError {
+    PeriodRequired,
+    NegativePeriod,
+    DuplicateTx(DuplicateTransactionError),
+}
+
+#[derive(Debug)]
+pub enum RefundError {
+    AccountLocked,
+    NoSuchAccount(ClientId),
+    NoSuchTransaction(u32),
+    NotADeposit(u32),
+    ClientMismatch(u32, ClientId),
     AmountRequired,
+    NegativeAmount,
+    ExceedsRefundable(f64, f64),
+    InsufficientFunds(f64, f64),
+}
+
+#[derive(Debug)]
+pub enum ExchangeError {
+    AccountLocked,
+    NoSuchAccount(ClientId),
+    AmountRequired,
+    NegativeAmount,
+    TargetCurrencyRequired,
+    SameCurrency(Currency),
+    DateRequired,
+    NoEffectiveRate(Currency, Currency, Date),
+    InsufficientFunds(f64, f64),
+    DuplicateTx(DuplicateTransactionError),
+    ScreeningHit(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InvariantViolation {
+    TotalMismatch(ClientId, f64, f64, f64),
+    NegativeHeld(ClientId, f64),
+    NegativeTotal(ClientId, f64),
+    UnbalancedJournal(Currency, f64, f64),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    Malformed(String),
+    MissingField(String, &'static str),
+    InvalidAmount(String, String),
+    InvalidIndicator(String, String),
+    UnmappedAccount(String, String),
+    ReferenceCollision(String, String),
 }
 
 #[derive(Debug)]
@@ -67,7 +148,24 @@ pub enum StatementError {
 
 impl fmt::Display for DuplicateTransactionError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "duplicate transaction id {} detected", self.tx_id)
+        match &self.key.source {
+            Some(source) => write!(
+                f,
+                "duplicate transaction id {} from source {} detected",
+                self.key.tx, source
+            ),
+            None => write!(f, "duplicate transaction id {} detected", self.key.tx),
+        }
+    }
+}
+
+impl fmt::Display for HouseAccountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "client id {} is reserved for the house account",
+            self.client
+        )
     }
 }
 
@@ -92,6 +190,19 @@ impl fmt::Display for DepositError {
             DepositError::NegativeDeposit => {
                 write!(f, "unable to deposit funds, amount is negative")
             }
+            DepositError::FeeExceedsAmount(fee, amount) => write!(
+                f,
+                "unable to deposit funds, fee exceeds the deposited amount fee={} amount={}",
+                fee, amount
+            ),
+            DepositError::ScreeningHit(reason) => {
+                write!(f, "unable to deposit funds, {}", reason)
+            }
+            DepositError::NoSuchAccount(id) => write!(
+                f,
+                "unable to deposit funds, client {} is not registered",
+                id
+            ),
         }
     }
 }
@@ -106,11 +217,11 @@ impl fmt::Display for WithdrawalError {
             WithdrawalError::AccountLocked => {
                 write!(f, "unable to withdraw funds, account is locked")
             }
-            WithdrawalError::InsufficientFunds(wanted, had) => {
+            WithdrawalError::InsufficientFunds(wanted, had, limit) => {
                 write!(
                     f,
-                    "insufficient funds to complete this transaction wanted={} had={}",
-                    wanted, had
+                    "insufficient funds to complete this transaction wanted={} had={} credit_limit={}",
+                    wanted, had, limit
                 )
             }
             WithdrawalError::NoSuchAccount(account) => {
@@ -120,6 +231,11 @@ impl fmt::Display for WithdrawalError {
                     account
                 )
             }
+            WithdrawalError::LimitExceeded(kind, limit, attempted) => write!(
+                f,
+                "withdrawal exceeds the {} limit limit={} attempted={}",
+                kind, limit, attempted
+            ),
             WithdrawalError::DuplicateTx(err) => write!(f, "failed to withdraw funds: {}", err),
             WithdrawalError::NegativeWithdrawal => {
                 write!(f, "unable to withdraw funds, amount is negative")
@@ -143,6 +259,11 @@ impl fmt::Display for DisputeError {
                 f,
                 "disputed transactions MUST have a specified amount, but none was present"
             ),
+            DisputeError::InsufficientFunds(wanted, had) => write!(
+                f,
+                "insufficient available funds to hold disputed amount wanted={} had={}",
+                wanted, had
+            ),
         }
     }
 }
@@ -185,6 +306,153 @@ impl fmt::Display for ChargebackError {
     }
 }
 
+impl fmt::Display for CreditLimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CreditLimitError::AmountRequired => write!(
+                f,
+                "credit limit transactions MUST specify an amount, but none was provided"
+            ),
+            CreditLimitError::NegativeLimit => {
+                write!(f, "unable to set credit limit, amount is negative")
+            }
+            CreditLimitError::BelowCreditUsed(limit, used) => write!(
+                f,
+                "unable to set credit limit below the credit in use limit={} used={}",
+                limit, used
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AccrualError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AccrualError::PeriodRequired => write!(
+                f,
+                "accrue transactions MUST specify the number of days in the period, but none was provided"
+            ),
+            AccrualError::NegativePeriod => {
+                write!(f, "unable to accrue interest, period is negative")
+            }
+            AccrualError::DuplicateTx(err) => write!(f, "failed to post interest: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for RefundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RefundError::AccountLocked => {
+                write!(f, "unable to refund transaction, account is locked")
+            }
+            RefundError::NoSuchAccount(id) => write!(
+                f,
+                "unable to refund transaction with non-existent account {}",
+                id
+            ),
+            RefundError::NoSuchTransaction(tx) => {
+                write!(f, "unable to refund non-existent transaction {}", tx)
+            }
+            RefundError::NotADeposit(tx) => {
+                write!(f, "unable to refund transaction {}, it is not a deposit", tx)
+            }
+            RefundError::ClientMismatch(tx, id) => write!(
+                f,
+                "unable to refund transaction {}, it does not belong to client {}",
+                tx, id
+            ),
+            RefundError::AmountRequired => write!(
+                f,
+                "transactions MUST have a specified amount in order to be refunded"
+            ),
+            RefundError::NegativeAmount => {
+                write!(f, "unable to refund transaction, amount is negative")
+            }
+            RefundError::ExceedsRefundable(wanted, refundable) => write!(
+                f,
+                "refunded amount exceeds the refundable amount of the transaction wanted={} refundable={}",
+                wanted, refundable
+            ),
+            RefundError::InsufficientFunds(wanted, had) => write!(
+                f,
+                "insufficient funds to complete this refund wanted={} had={}",
+                wanted, had
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeError::AccountLocked => {
+                write!(f, "unable to exchange funds, account is locked")
+            }
+            ExchangeError::NoSuchAccount(id) => {
+                write!(f, "unable to exchange funds, no such account: {}", id)
+            }
+            ExchangeError::AmountRequired => write!(
+                f,
+                "exchange transactions MUST specify an amount, but none was provided"
+            ),
+            ExchangeError::NegativeAmount => {
+                write!(f, "unable to exchange funds, amount is negative")
+            }
+            ExchangeError::TargetCurrencyRequired => write!(
+                f,
+                "exchange transactions MUST specify a currency to exchange to, but none was provided"
+            ),
+            ExchangeError::SameCurrency(currency) => write!(
+                f,
+                "unable to exchange funds, both currencies are {}",
+                currency
+            ),
+            ExchangeError::DateRequired => write!(
+                f,
+                "undated exchanges MUST follow a dated row to find the effective rate"
+            ),
+            ExchangeError::NoEffectiveRate(from, to, date) => write!(
+                f,
+                "no exchange rate from {} to {} is effective on {}",
+                from, to, date
+            ),
+            ExchangeError::InsufficientFunds(wanted, had) => write!(
+                f,
+                "insufficient funds to complete this exchange wanted={} had={}",
+                wanted, had
+            ),
+            ExchangeError::DuplicateTx(err) => write!(f, "failed to exchange funds: {}", err),
+            ExchangeError::ScreeningHit(reason) => {
+                write!(f, "unable to exchange funds, {}", reason)
+            }
+        }
+    }
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::TotalMismatch(id, available, held, total) => write!(
+                f,
+                "account {} total does not equal available plus held funds available={} held={} total={}",
+                id, available, held, total
+            ),
+            InvariantViolation::NegativeHeld(id, held) => {
+                write!(f, "account {} has negative held funds held={}", id, held)
+            }
+            InvariantViolation::NegativeTotal(id, total) => {
+                write!(f, "account {} has negative total funds total={}", id, total)
+            }
+            InvariantViolation::UnbalancedJournal(currency, debit, credit) => write!(
+                f,
+                "journal debits do not equal credits in {} debit={} credit={}",
+                currency, debit, credit
+            ),
+        }
+    }
+}
+
 impl fmt::Display for StatementError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -208,6 +476,27 @@ impl From<DuplicateTransactionError> for WithdrawalError {
     }
 }
 
+impl From<DuplicateTransactionError> for AccrualError {
+    fn from(err: DuplicateTransactionError) -> Self {
+        AccrualError::DuplicateTx(err)
+    }
+}
+
+impl From<DuplicateTransactionError> for ExchangeError {
+    fn from(err: DuplicateTransactionError) -> Self {
+        ExchangeError::DuplicateTx(err)
+    }
+}
+
+impl From<AccountError> for DepositError {
+    fn from(err: AccountError) -> Self {
+        match err {
+            AccountError::AccountLocked(_) => DepositError::AccountLocked,
+            AccountError::NoSuchAccount(id) => DepositError::NoSuchAccount(id),
+        }
+    }
+}
+
 impl From<AccountError> for DisputeError {
     fn from(err: AccountError) -> Self {
         match err {
@@ -235,6 +524,57 @@ impl From<AccountError> for ChargebackError {
     }
 }
 
+impl From<AccountError> for RefundError {
+    fn from(err: AccountError) -> Self {
+        match err {
+            AccountError::AccountLocked(_) => RefundError::AccountLocked,
+            AccountError::NoSuchAccount(id) => RefundError::NoSuchAccount(id),
+        }
+    }
+}
+
+impl From<AccountError> for ExchangeError {
+    fn from(err: AccountError) -> Self {
+        match err {
+            AccountError::AccountLocked(_) => ExchangeError::AccountLocked,
+            AccountError::NoSuchAccount(id) => ExchangeError::NoSuchAccount(id),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Malformed(reason) => write!(f, "malformed bank statement: {}", reason),
+            ImportError::MissingField(entry, field) => {
+                write!(f, "statement entry {} is missing its {}", entry, field)
+            }
+            ImportError::InvalidAmount(entry, amount) => {
+                write!(
+                    f,
+                    "statement entry {} has an invalid amount {}",
+                    entry, amount
+                )
+            }
+            ImportError::InvalidIndicator(entry, indicator) => write!(
+                f,
+                "statement entry {} has an unknown credit/debit indicator {}",
+                entry, indicator
+            ),
+            ImportError::UnmappedAccount(account, entry) => write!(
+                f,
+                "statement entry {} is for bank account {} which is not mapped to a client",
+                entry, account
+            ),
+            ImportError::ReferenceCollision(first, second) => write!(
+                f,
+                "statement entries {} and {} have references which map to the same tx id",
+                first, second
+            ),
+        }
+    }
+}
+
 impl From<csv::Error> for StatementError {
     fn from(err: csv::Error) -> Self {
         StatementError::SerializeError(err)
@@ -248,10 +588,17 @@ impl From<FromUtf8Error> for StatementError {
 }
 
 impl Error for DuplicateTransactionError {}
+impl Error for HouseAccountError {}
 impl Error for AccountError {}
 impl Error for DepositError {}
 impl Error for WithdrawalError {}
 impl Error for DisputeError {}
 impl Error for ResolveError {}
 impl Error for ChargebackError {}
+impl Error for CreditLimitError {}
+impl Error for AccrualError {}
+impl Error for RefundError {}
+impl Error for ExchangeError {}
+impl Error for InvariantViolation {}
+impl Error for ImportError {}
 impl Error for StatementError {}
@@ -0,0 +1,167 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::engine::TransactionType;
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// A single rule of a fee schedule. A rule applies to transactions of its
+/// type whose amount is at or above its threshold, so a tiered schedule is
+/// expressed as several rules for the same type with increasing thresholds.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FeeRule {
+    r#type: TransactionType,
+
+    /// The minimum transaction amount this rule applies to.
+    threshold: Option<f64>,
+
+    /// A flat fee charged per transaction.
+    flat: Option<f64>,
+
+    /// A fee charged as a percentage of the transaction amount.
+    percent: Option<f64>,
+
+    /// The minimum fee charged, if any.
+    min: Option<f64>,
+
+    /// The maximum fee charged, if any.
+    max: Option<f64>,
+}
+
+impl FeeRule {
+    fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or_default()
+    }
+
+    /// Calculates the fee this rule charges for the provided amount, rounded
+    /// to the precision of the provided currency.
+    fn calculate(&self, amount: f64, currency: Currency) -> f64 {
+        let mut fee =
+            self.flat.unwrap_or_default() + amount * self.percent.unwrap_or_default() / 100.0;
+
+        if let Some(min) = self.min {
+            fee = fee.max(min);
+        }
+
+        if let Some(max) = self.max {
+            fee = fee.min(max);
+        }
+
+        currency.round(fee)
+    }
+}
+
+/// A FeeSchedule determines the fees charged for each transaction processed
+/// by a Ledger.
+#[derive(Clone, Debug, Default)]
+pub struct FeeSchedule {
+    rules: Vec<FeeRule>,
+}
+
+impl FeeSchedule {
+    /// Attempts to read a fee schedule from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut rules = Vec::new();
+        for rule in iter.deserialize() {
+            rules.push(rule?);
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Calculates the fee for a transaction of the provided type and amount
+    /// using the rule with the highest threshold the amount qualifies for.
+    /// Transactions that no rule applies to are not charged a fee. Fees are
+    /// charged in the currency of the transaction.
+    pub fn fee_for(&self, r#type: &TransactionType, amount: f64, currency: Currency) -> f64 {
+        self.rules
+            .iter()
+            .filter(|rule| &rule.r#type == r#type && amount >= rule.threshold())
+            .max_by(|a, b| a.threshold().total_cmp(&b.threshold()))
+            .map_or(0.0, |rule| rule.calculate(amount, currency))
+    }
+}
+
+impl TryFrom<PathBuf> for FeeSchedule {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a fee schedule from the CSV file located at the
+    /// provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+/// A single itemized row of the fee report. Fees that are reversed, e.g. due
+/// to a chargeback, are reported with a negative fee.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FeeRecord {
+    pub tx: u32,
+    pub client: ClientId,
+    pub r#type: TransactionType,
+    pub currency: Currency,
+    pub amount: f64,
+    pub fee: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_calculate_flat_and_percentage_fees() {
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\n\
+             deposit,0,0.5,1,,\n\
+             withdrawal,0,1,,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let usd = Currency::default();
+
+        assert_eq!(schedule.fee_for(&TransactionType::Deposit, 100.0, usd), 1.5);
+        assert_eq!(
+            schedule.fee_for(&TransactionType::Withdrawal, 100.0, usd),
+            1.0
+        );
+        assert_eq!(schedule.fee_for(&TransactionType::Dispute, 100.0, usd), 0.0);
+    }
+
+    #[test]
+    fn should_calculate_tiered_fees_with_caps() {
+        let schedule = FeeSchedule::from_reader(
+            "type,threshold,flat,percent,min,max\n\
+             deposit,0,,2,0.25,\n\
+             deposit,1000,,1,,15\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let usd = Currency::default();
+
+        // Small deposits are charged the minimum fee.
+        assert_eq!(schedule.fee_for(&TransactionType::Deposit, 5.0, usd), 0.25);
+
+        // Deposits below the second tier are charged 2%.
+        assert_eq!(
+            schedule.fee_for(&TransactionType::Deposit, 500.0, usd),
+            10.0
+        );
+
+        // Deposits in the second tier are charged 1%, capped at 15.
+        assert_eq!(
+            schedule.fee_for(&TransactionType::Deposit, 1000.0, usd),
+            10.0
+        );
+        assert_eq!(
+            schedule.fee_for(&TransactionType::Deposit, 5000.0, usd),
+            15.0
+        );
+    }
+}
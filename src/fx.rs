@@ -0,0 +1,151 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::date::Date;
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// A single row of an exchange rate table. The rate is the number of units
+/// of the quote currency one unit of the base currency buys, and applies to
+/// exchanges dated on or after its effective date until it is superseded by a
+/// rate with a later effective date.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ExchangeRate {
+    base: Currency,
+    quote: Currency,
+    effective: Date,
+    rate: f64,
+
+    /// The spread charged on exchanges at this rate, as a percentage of the
+    /// converted amount.
+    spread: Option<f64>,
+}
+
+/// The rate and spread applied to a single exchange.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+    pub rate: f64,
+    pub spread: f64,
+}
+
+/// A RateTable holds the effective-dated exchange rates used to convert
+/// funds between currencies.
+#[derive(Clone, Debug, Default)]
+pub struct RateTable {
+    rates: Vec<ExchangeRate>,
+}
+
+impl RateTable {
+    /// Attempts to read an exchange rate table from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut rates = Vec::new();
+        for rate in iter.deserialize() {
+            rates.push(rate?);
+        }
+
+        Ok(Self { rates })
+    }
+
+    /// Returns the quote for converting from one currency to another that is
+    /// effective on the provided date, i.e. the rate with the latest effective
+    /// date on or before it. If the table only lists the opposite currency
+    /// pair, its inverse rate is used.
+    pub fn quote_for(&self, from: Currency, to: Currency, date: Date) -> Option<Quote> {
+        let effective = |base: Currency, quote: Currency| {
+            self.rates
+                .iter()
+                .filter(|rate| rate.base == base && rate.quote == quote)
+                .filter(|rate| rate.effective <= date)
+                .max_by_key(|rate| rate.effective)
+        };
+
+        if let Some(rate) = effective(from, to) {
+            return Some(Quote {
+                rate: rate.rate,
+                spread: rate.spread.unwrap_or_default(),
+            });
+        }
+
+        effective(to, from)
+            .filter(|rate| rate.rate > 0.0)
+            .map(|rate| Quote {
+                rate: 1.0 / rate.rate,
+                spread: rate.spread.unwrap_or_default(),
+            })
+    }
+}
+
+impl TryFrom<PathBuf> for RateTable {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read an exchange rate table from the CSV file located at
+    /// the provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+/// A single row of the exchange report, describing the rate used for an
+/// exchange and the spread revenue credited to the house account.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExchangeRecord {
+    pub tx: u32,
+    pub client: ClientId,
+    pub from: Currency,
+    pub to: Currency,
+
+    /// The amount debited in the currency exchanged from.
+    pub amount: f64,
+    pub rate: f64,
+
+    /// The amount credited to the client in the currency exchanged to.
+    pub converted: f64,
+
+    /// The spread revenue credited to the house account in the currency
+    /// exchanged to.
+    pub spread: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_use_effective_rate() {
+        let table = RateTable::from_reader(
+            "base,quote,effective,rate,spread\n\
+             EUR,USD,2021-01-01,1.2,0.5\n\
+             EUR,USD,2021-06-01,1.1,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let eur: Currency = "EUR".parse().unwrap();
+        let usd = Currency::default();
+
+        // The rate effective on the date of the exchange is used.
+        let quote = table.quote_for(eur, usd, "2021-03-01".parse().unwrap());
+        assert_eq!(
+            quote,
+            Some(Quote {
+                rate: 1.2,
+                spread: 0.5
+            })
+        );
+        let june = "2021-06-01".parse().unwrap();
+        assert_eq!(table.quote_for(eur, usd, june).unwrap().rate, 1.1);
+
+        // The inverse pair uses the inverse rate.
+        assert_eq!(table.quote_for(usd, eur, june).unwrap().rate, 1.0 / 1.1);
+
+        // No rate is effective before the earliest effective date.
+        let date = "2020-12-31".parse().unwrap();
+        assert_eq!(table.quote_for(eur, usd, date), None);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::client::ClientId;
+use crate::engine::TransactionType;
+use serde::Serialize;
+use std::fmt;
+use std::fmt::Formatter;
+use std::sync::Arc;
+
+/// A single row of the duplicates output, describing an input row that was
+/// skipped because an identical transaction had already been applied, e.g.
+/// because a partner re-sent a file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DuplicateRecord {
+    /// The row of the Ledger's input the duplicate was read at.
+    pub row: u64,
+    pub source: Option<Arc<str>>,
+    pub tx: u32,
+    pub r#type: TransactionType,
+    pub client: ClientId,
+    pub status: &'static str,
+}
+
+/// A single row of the batches output, describing a batch of input rows that
+/// was applied all-or-nothing and whether it was committed or rolled back.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BatchRecord {
+    pub source: Option<Arc<str>>,
+
+    /// The batch marked on the input rows, or none if the whole input file
+    /// was applied as a single batch.
+    pub batch: Option<Arc<str>>,
+
+    /// The first and last rows of the Ledger's input the batch spans.
+    pub first_row: u64,
+    pub last_row: u64,
+    pub status: &'static str,
+
+    /// Why the batch was rolled back, if it was.
+    pub reason: Option<String>,
+}
+
+/// A single row of the orphans output, describing a dispute, resolve or
+/// chargeback which referenced a transaction that never arrived within the
+/// reorder window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OrphanRecord {
+    /// The row of the Ledger's input the orphan was read at.
+    pub row: u64,
+    pub source: Option<Arc<str>>,
+    pub tx: u32,
+    pub r#type: TransactionType,
+    pub client: ClientId,
+
+    /// The row of the Ledger's input the orphan expired at.
+    pub expired_row: u64,
+}
+
+/// Why a dispute, resolve or chargeback was ignored. These rows are errors on
+/// our partner's side, and are reported back to them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartnerWarning {
+    /// The referenced transaction doesn't exist.
+    UnknownTx,
+
+    /// A resolve or chargeback referenced a transaction that isn't under
+    /// dispute.
+    NotDisputed,
+
+    /// A dispute referenced a transaction with nothing left to dispute.
+    AlreadyDisputed,
+
+    /// A dispute's amount exceeded what is left to dispute of the referenced
+    /// transaction.
+    ExceedsDisputable,
+
+    /// A resolve or chargeback's amount exceeded the disputed amount of the
+    /// referenced transaction.
+    ExceedsDisputed,
+
+    /// A dispute, resolve or chargeback specified a negative amount.
+    NegativeAmount,
+
+    /// A dispute was rejected because the client no longer had the disputed
+    /// funds available.
+    InsufficientFunds,
+}
+
+impl PartnerWarning {
+    pub const ALL: [PartnerWarning; 7] = [
+        PartnerWarning::UnknownTx,
+        PartnerWarning::NotDisputed,
+        PartnerWarning::AlreadyDisputed,
+        PartnerWarning::ExceedsDisputable,
+        PartnerWarning::ExceedsDisputed,
+        PartnerWarning::NegativeAmount,
+        PartnerWarning::InsufficientFunds,
+    ];
+}
+
+impl fmt::Display for PartnerWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PartnerWarning::UnknownTx => write!(f, "unknown_tx"),
+            PartnerWarning::NotDisputed => write!(f, "not_disputed"),
+            PartnerWarning::AlreadyDisputed => write!(f, "already_disputed"),
+            PartnerWarning::ExceedsDisputable => write!(f, "exceeds_disputable"),
+            PartnerWarning::ExceedsDisputed => write!(f, "exceeds_disputed"),
+            PartnerWarning::NegativeAmount => write!(f, "negative_amount"),
+            PartnerWarning::InsufficientFunds => write!(f, "insufficient_funds"),
+        }
+    }
+}
+
+/// A single row of the partner warnings output, describing a dispute,
+/// resolve or chargeback that was ignored.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WarningRecord {
+    /// The row of the Ledger's input the ignored row was read at.
+    pub row: u64,
+    pub source: Option<Arc<str>>,
+    pub tx: u32,
+    pub r#type: TransactionType,
+    pub client: ClientId,
+    pub warning: PartnerWarning,
+}
@@ -0,0 +1,154 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The source the deposits generated by accrual runs are namespaced by.
+pub const ACCRUAL_SOURCE: &str = "@accrual";
+
+/// The day count convention used to convert an accrual period into a
+/// fraction of a year.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DayCount {
+    /// The actual number of days in the period over a 360 day year.
+    Actual360,
+
+    /// The actual number of days in the period over a 365 day year.
+    #[default]
+    Actual365,
+}
+
+impl DayCount {
+    /// Returns the fraction of a year represented by the provided number of
+    /// days.
+    pub fn year_fraction(&self, days: f64) -> f64 {
+        match self {
+            DayCount::Actual360 => days / 360.0,
+            DayCount::Actual365 => days / 365.0,
+        }
+    }
+}
+
+impl FromStr for DayCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "act/360" => Ok(DayCount::Actual360),
+            "act/365" => Ok(DayCount::Actual365),
+            _ => Err(format!("unknown day count convention: {}", s)),
+        }
+    }
+}
+
+/// A single tier of an interest rate schedule. The tier's annual rate, in
+/// percent, applies to the portion of a balance above its threshold and below
+/// the threshold of the next tier.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RateTier {
+    threshold: f64,
+    rate: f64,
+}
+
+/// An InterestSchedule determines the interest accrued on the available
+/// balance of each account.
+#[derive(Clone, Debug, Default)]
+pub struct InterestSchedule {
+    tiers: Vec<RateTier>,
+    day_count: DayCount,
+}
+
+impl InterestSchedule {
+    /// Attempts to read the rate tiers of an interest schedule from the
+    /// provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R, day_count: DayCount) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut tiers = Vec::new();
+        for tier in iter.deserialize() {
+            tiers.push(tier?);
+        }
+        tiers.sort_by(|a: &RateTier, b: &RateTier| a.threshold.total_cmp(&b.threshold));
+
+        Ok(Self { tiers, day_count })
+    }
+
+    /// Attempts to read the rate tiers of an interest schedule from the CSV
+    /// file located at the provided PathBuf.
+    pub fn from_path(path: PathBuf, day_count: DayCount) -> Result<Self, Box<dyn Error>> {
+        Self::from_reader(std::fs::File::open(path)?, day_count)
+    }
+
+    /// Calculates the interest accrued on the provided balance over a period
+    /// of the provided number of days, rounded to the precision of the
+    /// balance's currency.
+    pub fn interest_for(&self, balance: f64, days: f64, currency: Currency) -> f64 {
+        let mut interest = 0.0;
+        for (i, tier) in self.tiers.iter().enumerate() {
+            if balance <= tier.threshold {
+                break;
+            }
+
+            let upper = self
+                .tiers
+                .get(i + 1)
+                .map_or(balance, |next| next.threshold.min(balance));
+            interest += (upper - tier.threshold) * tier.rate / 100.0;
+        }
+
+        currency.round(interest * self.day_count.year_fraction(days))
+    }
+}
+
+/// A single row of the accrual report, describing the interest posted to one
+/// account by an accrual run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccrualRecord {
+    /// The tx id of the accrual control row that triggered the run.
+    pub run: u32,
+
+    /// The tx id of the generated deposit the interest was posted as.
+    pub tx: u32,
+    pub client: ClientId,
+    pub currency: Currency,
+    pub balance: f64,
+    pub days: f64,
+    pub interest: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_accrue_tiered_interest() {
+        let schedule = InterestSchedule::from_reader(
+            "threshold,rate\n\
+             1000,1\n\
+             0,2\n"
+                .as_bytes(),
+            DayCount::Actual360,
+        )
+        .unwrap();
+        let usd = Currency::default();
+
+        // A balance within the first tier accrues 2% a year.
+        assert_eq!(schedule.interest_for(500.0, 360.0, usd), 10.0);
+
+        // The portion of a balance above 1000 accrues 1% a year.
+        assert_eq!(schedule.interest_for(3000.0, 360.0, usd), 40.0);
+
+        // Interest is prorated by the day count convention.
+        assert_eq!(schedule.interest_for(3000.0, 30.0, usd), 3.3333);
+
+        // Non-positive balances don't accrue interest.
+        assert_eq!(schedule.interest_for(-100.0, 30.0, usd), 0.0);
+    }
+}
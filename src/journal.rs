@@ -0,0 +1,258 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::errors::InvariantViolation;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// An account in the Ledger's chart of accounts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LedgerAccount {
+    /// The funds a client has available, which the Ledger owes to them.
+    ClientLiability(ClientId),
+
+    /// The funds of a client that are held while under dispute.
+    Suspense(ClientId),
+
+    /// Funds reclaimed through chargebacks, offset by what is recovered from
+    /// the client's held funds.
+    ChargebackLoss,
+
+    /// The funds settled with our banking partners, e.g. by deposits and
+    /// withdrawals.
+    Settlement,
+
+    /// The interest paid to clients by accrual runs.
+    InterestExpense,
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::ClientLiability(client) => write!(f, "client_liability:{}", client),
+            LedgerAccount::Suspense(client) => write!(f, "suspense:{}", client),
+            LedgerAccount::ChargebackLoss => write!(f, "chargeback_loss"),
+            LedgerAccount::Settlement => write!(f, "settlement"),
+            LedgerAccount::InterestExpense => write!(f, "interest_expense"),
+        }
+    }
+}
+
+impl Serialize for LedgerAccount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A single balanced journal entry, debiting one account and crediting
+/// another by the same amount.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JournalEntry {
+    /// The tx id of the transaction that caused the entry.
+    pub tx: u32,
+    pub currency: Currency,
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: f64,
+}
+
+/// A single row of the trial balance, describing the net balance of one
+/// account in one currency.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TrialBalanceRow {
+    pub account: String,
+    pub currency: Currency,
+    pub debit: f64,
+    pub credit: f64,
+}
+
+/// The running debit and credit totals of an account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Totals {
+    debit: f64,
+    credit: f64,
+}
+
+/// A double-entry Journal records every movement of funds as a balanced
+/// entry against the chart of accounts, so that the sum of all debits always
+/// equals the sum of all credits in each currency.
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    totals: HashMap<(LedgerAccount, Currency), Totals>,
+}
+
+impl Journal {
+    /// Posts an entry moving the provided amount from the credited account
+    /// to the debited account. Negative amounts are posted with the accounts
+    /// swapped, and zero amounts are not posted at all.
+    pub fn post(
+        &mut self,
+        tx: u32,
+        currency: Currency,
+        debit: LedgerAccount,
+        credit: LedgerAccount,
+        amount: f64,
+    ) {
+        let amount = currency.round(amount);
+        if amount == 0.0 {
+            return;
+        }
+        if amount < 0.0 {
+            return self.post(tx, currency, credit, debit, -amount);
+        }
+
+        let totals = self.totals.entry((debit.clone(), currency)).or_default();
+        totals.debit = currency.round(totals.debit + amount);
+        let totals = self.totals.entry((credit.clone(), currency)).or_default();
+        totals.credit = currency.round(totals.credit + amount);
+
+        self.entries.push(JournalEntry {
+            tx,
+            currency,
+            debit,
+            credit,
+            amount,
+        });
+    }
+
+    /// Returns the credit balance of the provided account, i.e. its credits
+    /// minus its debits. Liabilities, such as the funds owed to clients, have
+    /// a positive credit balance.
+    pub fn credit_balance(&self, account: &LedgerAccount, currency: Currency) -> f64 {
+        self.totals
+            .get(&(account.clone(), currency))
+            .map_or(0.0, |totals| currency.round(totals.credit - totals.debit))
+    }
+
+    /// Removes the entries posted after the first `len`, reversing their
+    /// effect on the account totals.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.entries.len() {
+            return;
+        }
+
+        for entry in self.entries.split_off(len).into_iter().rev() {
+            let currency = entry.currency;
+            for (account, debit) in [(entry.debit, true), (entry.credit, false)] {
+                let key = (account, currency);
+                if let Some(totals) = self.totals.get_mut(&key) {
+                    if debit {
+                        totals.debit = currency.round(totals.debit - entry.amount);
+                    } else {
+                        totals.credit = currency.round(totals.credit - entry.amount);
+                    }
+
+                    // Accounts first posted to by the removed entries are
+                    // removed too, since posted entries are never zero.
+                    if totals.debit == 0.0 && totals.credit == 0.0 {
+                        self.totals.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Returns the trial balance of the journal, one row per account and
+    /// currency with the account's net balance in its debit or credit column.
+    pub fn trial_balance(&self) -> Vec<TrialBalanceRow> {
+        let mut keys: Vec<_> = self.totals.keys().collect();
+        keys.sort_unstable_by_key(|(account, currency)| (*currency, account));
+
+        keys.into_iter()
+            .map(|(account, currency)| {
+                let balance = self.credit_balance(account, *currency);
+                TrialBalanceRow {
+                    account: account.to_string(),
+                    currency: *currency,
+                    debit: if balance < 0.0 { -balance } else { 0.0 },
+                    credit: if balance > 0.0 { balance } else { 0.0 },
+                }
+            })
+            .collect()
+    }
+
+    /// Verifies that the debits and credits of the journal net to zero in
+    /// every currency.
+    pub fn check_balanced(&self) -> Result<(), InvariantViolation> {
+        let mut sums: HashMap<Currency, Totals> = HashMap::new();
+        for ((_, currency), totals) in self.totals.iter() {
+            let sum = sums.entry(*currency).or_default();
+            sum.debit += totals.debit;
+            sum.credit += totals.credit;
+        }
+
+        for (currency, sum) in sums {
+            if currency.round(sum.debit) != currency.round(sum.credit) {
+                return Err(InvariantViolation::UnbalancedJournal(
+                    currency,
+                    currency.round(sum.debit),
+                    currency.round(sum.credit),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_post_balanced_entries() {
+        let mut journal = Journal::default();
+        let usd = Currency::default();
+
+        // Deposit 10, hold 4 of it for a dispute, then post a negative
+        // amount which reverses the accounts.
+        journal.post(
+            1,
+            usd,
+            LedgerAccount::Settlement,
+            LedgerAccount::ClientLiability(ClientId::Numeric(1)),
+            10.0,
+        );
+        journal.post(
+            2,
+            usd,
+            LedgerAccount::ClientLiability(ClientId::Numeric(1)),
+            LedgerAccount::Suspense(ClientId::Numeric(1)),
+            4.0,
+        );
+        journal.post(
+            3,
+            usd,
+            LedgerAccount::Suspense(ClientId::Numeric(1)),
+            LedgerAccount::ClientLiability(ClientId::Numeric(1)),
+            -1.0,
+        );
+
+        assert_eq!(
+            journal.credit_balance(&LedgerAccount::ClientLiability(ClientId::Numeric(1)), usd),
+            5.0
+        );
+        assert_eq!(
+            journal.credit_balance(&LedgerAccount::Suspense(ClientId::Numeric(1)), usd),
+            5.0
+        );
+        assert_eq!(
+            journal.credit_balance(&LedgerAccount::Settlement, usd),
+            -10.0
+        );
+        assert_eq!(journal.entries().len(), 3);
+        assert!(journal.check_balanced().is_ok());
+
+        // The trial balance reports each account's net balance.
+        let debits: f64 = journal.trial_balance().iter().map(|row| row.debit).sum();
+        let credits: f64 = journal.trial_balance().iter().map(|row| row.credit).sum();
+        assert_eq!(debits, 10.0);
+        assert_eq!(credits, 10.0);
+    }
+}
@@ -1,3 +1,20 @@
 pub mod args;
+pub mod camt;
+pub mod client;
+pub mod currency;
+pub mod date;
+pub mod dialect;
 pub mod engine;
 pub mod errors;
+pub mod fees;
+pub mod fx;
+pub mod ingest;
+pub mod interest;
+pub mod journal;
+pub mod limits;
+pub mod monitoring;
+pub mod mt940;
+pub mod registry;
+pub mod risk;
+pub mod screening;
+pub mod validate;
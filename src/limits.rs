@@ -0,0 +1,228 @@
+use crate::currency::Currency;
+use csv::Trim;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::path::PathBuf;
+
+/// The tier of accounts that don't have a tier configured.
+pub const DEFAULT_TIER: &str = "default";
+
+/// The withdrawal limits of a single account tier in a single currency. Count
+/// and sum limits apply over a rolling window of the most recent rows
+/// processed by the Ledger and/or the most recent days. Without a window they
+/// apply to the whole run.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct WithdrawalLimit {
+    tier: String,
+
+    /// The currency the limit's amounts are in, or the default currency if
+    /// none was specified. Withdrawals in other currencies aren't limited by
+    /// it.
+    #[serde(default)]
+    currency: Option<Currency>,
+
+    /// The maximum amount of a single withdrawal.
+    max_single: Option<f64>,
+
+    /// The maximum number of withdrawals within the window.
+    max_count: Option<usize>,
+
+    /// The maximum sum of withdrawals within the window.
+    max_sum: Option<f64>,
+
+    /// The number of most recently processed rows the window spans.
+    window_rows: Option<u64>,
+
+    /// The number of days the window spans, including the day of the
+    /// withdrawal. Only applies to dated withdrawals.
+    window_days: Option<i64>,
+}
+
+/// Identifies which withdrawal limit a withdrawal exceeded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitKind {
+    MaxSingle,
+    MaxCount,
+    MaxSum,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitKind::MaxSingle => write!(f, "max_single"),
+            LimitKind::MaxCount => write!(f, "max_count"),
+            LimitKind::MaxSum => write!(f, "max_sum"),
+        }
+    }
+}
+
+/// A withdrawal that counts towards the rolling window limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecentWithdrawal {
+    /// The row of the Ledger's input the withdrawal was processed at.
+    pub row: u64,
+
+    /// The day of the withdrawal, as days since the epoch, if it was dated.
+    pub day: Option<i64>,
+    pub amount: f64,
+}
+
+impl WithdrawalLimit {
+    /// Removes withdrawals which have fallen out of the rolling window ending
+    /// at the provided row and day.
+    fn expire(&self, recent: &mut VecDeque<RecentWithdrawal>, row: u64, day: Option<i64>) {
+        while let Some(oldest) = recent.front() {
+            let expired_row = self
+                .window_rows
+                .is_some_and(|rows| oldest.row + rows <= row);
+            let expired_day = match (self.window_days, oldest.day, day) {
+                (Some(days), Some(oldest), Some(day)) => oldest + days <= day,
+                _ => false,
+            };
+
+            if !(expired_row || expired_day) {
+                break;
+            }
+            recent.pop_front();
+        }
+    }
+
+    /// Checks whether a withdrawal of the provided amount would exceed this
+    /// limit given the client's recent withdrawals, returning the limit that
+    /// was exceeded along with its value and the value the withdrawal would
+    /// have reached.
+    pub fn check(
+        &self,
+        recent: &mut VecDeque<RecentWithdrawal>,
+        row: u64,
+        day: Option<i64>,
+        amount: f64,
+    ) -> Result<(), (LimitKind, f64, f64)> {
+        if let Some(max) = self.max_single.filter(|max| amount > *max) {
+            return Err((LimitKind::MaxSingle, max, amount));
+        }
+
+        self.expire(recent, row, day);
+
+        if let Some(max) = self.max_count.filter(|max| recent.len() + 1 > *max) {
+            return Err((LimitKind::MaxCount, max as f64, (recent.len() + 1) as f64));
+        }
+
+        let sum = recent.iter().map(|w| w.amount).sum::<f64>() + amount;
+        if let Some(max) = self.max_sum.filter(|max| sum > *max) {
+            return Err((LimitKind::MaxSum, max, sum));
+        }
+
+        Ok(())
+    }
+}
+
+/// The withdrawal limits of every account tier, keyed by tier and currency.
+#[derive(Clone, Debug, Default)]
+pub struct WithdrawalLimits {
+    limits: HashMap<String, HashMap<Currency, WithdrawalLimit>>,
+}
+
+impl WithdrawalLimits {
+    /// Attempts to read withdrawal limits from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut limits: HashMap<String, HashMap<_, _>> = HashMap::new();
+        for limit in iter.deserialize() {
+            let limit: WithdrawalLimit = limit?;
+            let currency = limit.currency.unwrap_or_default();
+            limits
+                .entry(limit.tier.clone())
+                .or_default()
+                .insert(currency, limit);
+        }
+
+        Ok(Self { limits })
+    }
+
+    /// Returns the limits of the provided tier in the provided currency,
+    /// falling back to the limits of the default tier in that currency.
+    pub fn for_tier(&self, tier: Option<&str>, currency: Currency) -> Option<&WithdrawalLimit> {
+        let get = |tier: &str| self.limits.get(tier)?.get(&currency);
+        tier.and_then(get).or_else(|| get(DEFAULT_TIER))
+    }
+}
+
+impl TryFrom<PathBuf> for WithdrawalLimits {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read withdrawal limits from the CSV file located at the
+    /// provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_enforce_rolling_window_limits() {
+        let limits = WithdrawalLimits::from_reader(
+            "tier,currency,max_single,max_count,max_sum,window_rows,window_days\n\
+             default,,100,2,,3,\n\
+             default,JPY,10000,,,,\n\
+             gold,,1000,,500,,1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let usd = Currency::default();
+
+        // Limits are keyed by currency, and currencies without limits aren't
+        // limited.
+        let jpy = "JPY".parse().unwrap();
+        let limit = limits.for_tier(None, jpy).unwrap();
+        assert!(limit.check(&mut VecDeque::new(), 1, None, 5000.0).is_ok());
+        assert!(limits.for_tier(None, "EUR".parse().unwrap()).is_none());
+
+        // Unknown tiers fall back to the default tier.
+        let limit = limits.for_tier(Some("silver"), usd).unwrap();
+        let mut recent = VecDeque::new();
+        assert_eq!(
+            limit.check(&mut recent, 1, None, 150.0),
+            Err((LimitKind::MaxSingle, 100.0, 150.0))
+        );
+
+        // At most two withdrawals within three rows.
+        for row in [1, 2] {
+            assert!(limit.check(&mut recent, row, None, 10.0).is_ok());
+            recent.push_back(RecentWithdrawal {
+                row,
+                day: None,
+                amount: 10.0,
+            });
+        }
+        assert_eq!(
+            limit.check(&mut recent, 3, None, 10.0),
+            Err((LimitKind::MaxCount, 2.0, 3.0))
+        );
+        assert!(limit.check(&mut recent, 4, None, 10.0).is_ok());
+
+        // Gold accounts may withdraw at most 500 per day.
+        let limit = limits.for_tier(Some("gold"), usd).unwrap();
+        let mut recent = VecDeque::from([RecentWithdrawal {
+            row: 1,
+            day: Some(100),
+            amount: 400.0,
+        }]);
+        assert_eq!(
+            limit.check(&mut recent, 2, Some(100), 200.0),
+            Err((LimitKind::MaxSum, 500.0, 600.0))
+        );
+        assert!(limit.check(&mut recent, 3, Some(101), 200.0).is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::client::ClientId;
+use serde::Serialize;
+
+/// Determines when chargebacks lock a client's account. Accounts with
+/// chargebacks below the thresholds are placed under review instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChargebackPolicy {
+    /// The number of chargebacks that locks an account, if any.
+    pub lock_count: Option<u32>,
+
+    /// The ratio of chargebacks to deposits that locks an account, if any.
+    pub lock_ratio: Option<f64>,
+
+    /// The number of deposits a client must have made before the ratio
+    /// threshold applies.
+    pub min_deposits: u32,
+}
+
+impl Default for ChargebackPolicy {
+    /// By default an account is locked on its first chargeback.
+    fn default() -> Self {
+        Self {
+            lock_count: Some(1),
+            lock_ratio: None,
+            min_deposits: 0,
+        }
+    }
+}
+
+impl ChargebackPolicy {
+    /// Returns whether a client with the provided metrics should be locked.
+    pub fn should_lock(&self, metrics: &ClientMetrics) -> bool {
+        let over_count = self
+            .lock_count
+            .is_some_and(|count| metrics.chargebacks >= count);
+        let over_ratio = self.lock_ratio.is_some_and(|ratio| {
+            metrics.deposits >= self.min_deposits && metrics.chargeback_ratio() >= ratio
+        });
+
+        over_count || over_ratio
+    }
+}
+
+/// The dispute and chargeback counts of a single client.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClientMetrics {
+    pub deposits: u32,
+    pub disputes: u32,
+    pub chargebacks: u32,
+}
+
+impl ClientMetrics {
+    fn ratio(&self, count: u32) -> f64 {
+        if self.deposits == 0 {
+            0.0
+        } else {
+            f64::from(count) / f64::from(self.deposits)
+        }
+    }
+
+    pub fn dispute_ratio(&self) -> f64 {
+        self.ratio(self.disputes)
+    }
+
+    pub fn chargeback_ratio(&self) -> f64 {
+        self.ratio(self.chargebacks)
+    }
+}
+
+/// A single row of the monitoring report, describing the dispute and
+/// chargeback ratios of a client.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MonitoringRecord {
+    pub client: ClientId,
+    pub deposits: u32,
+    pub disputes: u32,
+    pub chargebacks: u32,
+    pub dispute_ratio: f64,
+    pub chargeback_ratio: f64,
+    pub review: bool,
+    pub locked: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_lock_past_thresholds() {
+        let metrics = ClientMetrics {
+            deposits: 10,
+            disputes: 3,
+            chargebacks: 2,
+        };
+        assert_eq!(metrics.dispute_ratio(), 0.3);
+        assert_eq!(metrics.chargeback_ratio(), 0.2);
+
+        // The default policy locks on the first chargeback.
+        assert!(ChargebackPolicy::default().should_lock(&metrics));
+
+        // A ratio threshold only applies once enough deposits were made.
+        let policy = ChargebackPolicy {
+            lock_count: None,
+            lock_ratio: Some(0.2),
+            min_deposits: 20,
+        };
+        assert!(!policy.should_lock(&metrics));
+        let policy = ChargebackPolicy {
+            min_deposits: 10,
+            ..policy
+        };
+        assert!(policy.should_lock(&metrics));
+
+        // Clients below every threshold aren't locked.
+        let policy = ChargebackPolicy {
+            lock_count: Some(3),
+            lock_ratio: Some(0.5),
+            min_deposits: 0,
+        };
+        assert!(!policy.should_lock(&metrics));
+    }
+}
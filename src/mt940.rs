@@ -0,0 +1,455 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::engine::{Transaction, TransactionType};
+use crate::errors::ImportError;
+use crate::registry::BankAccounts;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A booked balance of an MT940 statement, i.e. a `:60F:` opening or `:62F:`
+/// closing balance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mt940Balance {
+    pub credit: bool,
+    pub date: Date,
+    pub currency: Currency,
+    pub amount: f64,
+}
+
+impl Mt940Balance {
+    /// Returns the balance as a signed amount, debit balances being
+    /// negative.
+    pub fn signed(&self) -> f64 {
+        if self.credit {
+            self.amount
+        } else {
+            -self.amount
+        }
+    }
+}
+
+/// A `:61:` statement line, along with the `:86:` narrative following it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mt940Line {
+    pub value_date: Date,
+
+    /// Whether the line credits the account. Reversals of a debit credit the
+    /// account, and reversals of a credit debit it.
+    pub credit: bool,
+    pub amount: f64,
+
+    /// The transaction type identification code, e.g. `NTRF`.
+    pub type_code: String,
+
+    /// The reference for the account owner, and the bank's reference if any.
+    /// An empty or `NONREF` bank reference is treated as missing.
+    pub reference: String,
+    pub bank_reference: Option<String>,
+    pub narrative: Option<String>,
+}
+
+/// A single statement of an MT940 file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mt940Statement {
+    /// The `:20:` transaction reference number.
+    pub reference: String,
+
+    /// The `:25:` account identification.
+    pub account: String,
+
+    /// The `:28C:` statement and sequence number.
+    pub number: String,
+    pub opening: Option<Mt940Balance>,
+    pub closing: Option<Mt940Balance>,
+    pub lines: Vec<Mt940Line>,
+}
+
+impl Mt940Statement {
+    /// Returns the source the statement's transactions are namespaced by.
+    fn source(&self) -> String {
+        format!("{}/{}", self.account, self.number)
+    }
+
+    /// Returns the client the statement's account is mapped to.
+    pub fn client<'a>(&self, accounts: &'a BankAccounts) -> Result<&'a ClientId, ImportError> {
+        accounts.client(&self.account).ok_or_else(|| {
+            ImportError::UnmappedAccount(self.account.clone(), self.reference.clone())
+        })
+    }
+
+    /// Attempts to convert the statement lines into deposits for credits and
+    /// withdrawals for debits of the client the statement's account is mapped
+    /// to, carrying the narrative of each line as its memo.
+    ///
+    /// Transactions are namespaced by the statement's account and number, and
+    /// their tx id is derived from the bank's reference for their line, or
+    /// its position within the statement if it has none or shares it with
+    /// another line, so that importing the same statement again is a no-op.
+    /// No transactions are returned if two lines are keyed to the same tx id.
+    pub fn transactions(&self, accounts: &BankAccounts) -> Result<Vec<Transaction>, ImportError> {
+        let client = self.client(accounts)?;
+        let source: Arc<str> = Arc::from(self.source());
+        let currency = self
+            .opening
+            .or(self.closing)
+            .map(|balance| balance.currency);
+
+        let mut shared: HashMap<&str, usize> = HashMap::new();
+        for reference in self
+            .lines
+            .iter()
+            .filter_map(|line| line.bank_reference.as_deref())
+        {
+            *shared.entry(reference).or_default() += 1;
+        }
+
+        let mut keys: HashMap<u32, String> = HashMap::new();
+        let mut transactions = Vec::with_capacity(self.lines.len());
+        for (index, line) in self.lines.iter().enumerate() {
+            let r#type = if line.credit {
+                TransactionType::Deposit
+            } else {
+                TransactionType::Withdrawal
+            };
+
+            let key = match line.bank_reference.as_deref() {
+                Some(reference) if shared[reference] == 1 => reference.to_string(),
+                _ => format!("#{}", index + 1),
+            };
+            let tx = Transaction::tx_for_reference(&key);
+            if let Some(other) = keys.insert(tx, key.clone()) {
+                return Err(ImportError::ReferenceCollision(other, key));
+            }
+
+            let mut transaction = Transaction::new(r#type, client.clone(), tx, Some(line.amount))
+                .with_source(source.clone())
+                .with_date(line.value_date)
+                .from_bank_statement();
+            if let Some(currency) = currency {
+                transaction = transaction.with_currency(currency);
+            }
+            if let Some(narrative) = &line.narrative {
+                transaction = transaction.with_memo(narrative.clone());
+            }
+            transactions.push(transaction);
+        }
+
+        Ok(transactions)
+    }
+}
+
+/// A single row of the reconciliation output, describing a statement whose
+/// opening or closing balance didn't match the balance of the client's
+/// account.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReconciliationRecord {
+    pub statement: String,
+    pub account: String,
+    pub client: ClientId,
+    pub currency: Currency,
+
+    /// Which balance of the statement didn't match, `opening` or `closing`.
+    pub balance: &'static str,
+    pub expected: f64,
+    pub actual: f64,
+}
+
+/// Splits the text of a message into its fields, each a tag and its possibly
+/// multi-line content. SWIFT block wrappers are skipped.
+fn fields(text: &str) -> Vec<(&str, String)> {
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        let tag = line
+            .strip_prefix(':')
+            .and_then(|rest| rest.split_once(':'))
+            .filter(|(tag, _)| {
+                (2..=3).contains(&tag.len()) && tag.bytes().take(2).all(|b| b.is_ascii_digit())
+            });
+
+        match tag {
+            Some((tag, content)) => fields.push((tag, content.to_string())),
+            // Only a line that is exactly "-", or "-}" in a SWIFT block,
+            // terminates the message, so narrative lines starting with a
+            // dash are kept.
+            None if line.starts_with('{') || matches!(line.trim(), "" | "-" | "-}") => {}
+            None => {
+                if let Some((_, content)) = fields.last_mut() {
+                    content.push('\n');
+                    content.push_str(line);
+                }
+            }
+        }
+    }
+
+    fields
+}
+
+/// Parses a YYMMDD date, assuming the 21st century.
+fn parse_date(text: &str) -> Option<Date> {
+    let year = text.get(0..2)?.parse::<i32>().ok()?;
+    let month = text.get(2..4)?.parse().ok()?;
+    let day = text.get(4..6)?.parse().ok()?;
+    Date::new(2000 + year, month, day).ok()
+}
+
+/// Parses an amount using a decimal comma, e.g. `1234,56`.
+fn parse_amount(text: &str) -> Option<f64> {
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit() || b == b',') {
+        return None;
+    }
+    text.replacen(',', ".", 1).parse().ok()
+}
+
+fn parse_balance(tag: &str, content: &str) -> Result<Mt940Balance, ImportError> {
+    let invalid = || ImportError::Malformed(format!("invalid :{}: balance {}", tag, content));
+    let credit = match content.get(0..1) {
+        Some("C") => true,
+        Some("D") => false,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Mt940Balance {
+        credit,
+        date: content.get(1..7).and_then(parse_date).ok_or_else(invalid)?,
+        currency: content
+            .get(7..10)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(invalid)?,
+        amount: content
+            .get(10..)
+            .map(str::trim)
+            .and_then(parse_amount)
+            .ok_or_else(invalid)?,
+    })
+}
+
+fn parse_line(content: &str) -> Result<Mt940Line, ImportError> {
+    let invalid = |reason: &str| {
+        let first = content.lines().next().unwrap_or_default();
+        ImportError::Malformed(format!("invalid :61: statement line {}: {}", first, reason))
+    };
+    let (first, _supplementary) = content.split_once('\n').unwrap_or((content, ""));
+
+    let value_date = first
+        .get(0..6)
+        .and_then(parse_date)
+        .ok_or_else(|| invalid("bad value date"))?;
+    let mut rest = &first[6..];
+
+    // The entry date is optional.
+    if rest.len() >= 4 && rest.as_bytes()[..4].iter().all(u8::is_ascii_digit) {
+        rest = &rest[4..];
+    }
+
+    let (credit, mark) = if rest.starts_with("RC") {
+        (false, 2)
+    } else if rest.starts_with("RD") {
+        (true, 2)
+    } else if rest.starts_with('C') {
+        (true, 1)
+    } else if rest.starts_with('D') {
+        (false, 1)
+    } else {
+        return Err(invalid("bad debit/credit mark"));
+    };
+    rest = &rest[mark..];
+
+    // The funds code, i.e. the third letter of the currency code, is
+    // optional.
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        rest = &rest[1..];
+    }
+
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .unwrap_or(rest.len());
+    let amount = parse_amount(&rest[..end]).ok_or_else(|| invalid("bad amount"))?;
+    rest = &rest[end..];
+
+    let type_code = rest
+        .get(0..4)
+        .ok_or_else(|| invalid("missing transaction type"))?
+        .to_string();
+    rest = &rest[4..];
+
+    let (reference, bank_reference) = match rest.split_once("//") {
+        Some((reference, bank)) => (reference, bank.trim()),
+        None => (rest, ""),
+    };
+    let bank_reference = match bank_reference {
+        "" | "NONREF" => None,
+        bank_reference => Some(bank_reference.to_string()),
+    };
+
+    Ok(Mt940Line {
+        value_date,
+        credit,
+        amount,
+        type_code,
+        reference: reference.trim().to_string(),
+        bank_reference,
+        narrative: None,
+    })
+}
+
+/// Attempts to parse every statement of an MT940 file.
+pub fn parse(text: &str) -> Result<Vec<Mt940Statement>, ImportError> {
+    let mut statements: Vec<Mt940Statement> = Vec::new();
+    for (tag, content) in fields(text) {
+        // Every statement starts with its transaction reference.
+        if tag == "20" {
+            statements.push(Mt940Statement {
+                reference: content.trim().to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let statement = statements.last_mut().ok_or_else(|| {
+            ImportError::Malformed(format!(":{}: field before the first :20: field", tag))
+        })?;
+
+        match tag {
+            "25" => statement.account = content.trim().to_string(),
+            "28C" | "28" => statement.number = content.trim().to_string(),
+            "60F" | "60M" => statement.opening = Some(parse_balance(tag, &content)?),
+            "62F" | "62M" => statement.closing = Some(parse_balance(tag, &content)?),
+            "61" => statement.lines.push(parse_line(&content)?),
+            "86" => {
+                // A narrative following a statement line describes it,
+                // otherwise it describes the statement and is ignored.
+                let narrative = content.split_whitespace().collect::<Vec<_>>().join(" ");
+                if let Some(line) = statement.lines.last_mut() {
+                    if line.narrative.is_none() {
+                        line.narrative = Some(narrative);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for statement in statements.iter() {
+        if statement.account.is_empty() {
+            return Err(ImportError::MissingField(
+                statement.reference.clone(),
+                "account identification",
+            ));
+        }
+    }
+
+    Ok(statements)
+}
+
+/// Attempts to parse every statement of the MT940 file located at the
+/// provided PathBuf.
+pub fn parse_file(path: PathBuf) -> Result<Vec<Mt940Statement>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(parse(&text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "{1:F01BANKDEFFXXXX0000000000}{2:I940BANKDEFFXXXXN}{4:\n\
+                             :20:STARTUMS\n\
+                             :25:DE89370400440532013000\n\
+                             :28C:00001/001\n\
+                             :60F:C240301EUR1000,00\n\
+                             :61:2403010301CR250,50NTRFINV-123//B0001\n\
+                             :86:Invoice 123\n\
+                             -Smith and Sons\n\
+                             :61:240302RD100,NCHKNONREF\n\
+                             :61:240303D50,25NMSCNONREF\n\
+                             :62F:C240303EUR1300,25\n\
+                             -}";
+
+    #[test]
+    fn should_parse_statement_lines() {
+        let statements = parse(STATEMENT).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let statement = &statements[0];
+        assert_eq!(statement.account, "DE89370400440532013000");
+        assert_eq!(statement.number, "00001/001");
+        assert_eq!(statement.opening.unwrap().signed(), 1000.0);
+        assert_eq!(statement.closing.unwrap().signed(), 1300.25);
+
+        // A credit with an entry date and funds code, a reversal of a debit,
+        // and a debit.
+        let lines: Vec<_> = statement
+            .lines
+            .iter()
+            .map(|line| (line.credit, line.amount, line.reference.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (true, 250.5, "INV-123"),
+                (true, 100.0, "NONREF"),
+                (false, 50.25, "NONREF")
+            ]
+        );
+        assert_eq!(statement.lines[0].bank_reference.as_deref(), Some("B0001"));
+        assert_eq!(
+            statement.lines[0].narrative.as_deref(),
+            Some("Invoice 123 -Smith and Sons")
+        );
+
+        // The lines become transactions of the mapped client.
+        let accounts =
+            BankAccounts::from_reader("account,client\nDE89370400440532013000,7\n".as_bytes())
+                .unwrap();
+        let transactions = statement.transactions(&accounts).unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(
+            transactions[2].transaction_type(),
+            &TransactionType::Withdrawal
+        );
+        assert_eq!(transactions[0].memo(), Some("Invoice 123 -Smith and Sons"));
+
+        // Lines are keyed by the bank's reference, or their position if they
+        // have none.
+        assert_eq!(transactions[0].tx(), Transaction::tx_for_reference("B0001"));
+        assert_eq!(transactions[1].tx(), Transaction::tx_for_reference("#2"));
+        assert_eq!(
+            transactions[0].source(),
+            Some("DE89370400440532013000/00001/001")
+        );
+        assert!(statement.transactions(&BankAccounts::default()).is_err());
+    }
+
+    #[test]
+    fn should_key_lines_without_a_unique_reference_by_position() {
+        let statements = parse(
+            ":20:STARTUMS\n\
+             :25:DE89370400440532013000\n\
+             :28C:00002/001\n\
+             :61:240301C10,NTRFA//B0001\n\
+             :61:240302C20,NTRFB//B0001\n\
+             :61:240303C30,NTRFC//\n\
+             :61:240304C40,NTRFD//NONREF\n\
+             :61:240305C50,NTRFE//B0002\n",
+        )
+        .unwrap();
+        let accounts =
+            BankAccounts::from_reader("account,client\nDE89370400440532013000,7\n".as_bytes())
+                .unwrap();
+        let transactions = statements[0].transactions(&accounts).unwrap();
+
+        // Lines sharing a bank reference, and lines with an empty or NONREF
+        // reference, are keyed by their position.
+        let txs: Vec<u32> = transactions.iter().map(Transaction::tx).collect();
+        assert_eq!(
+            txs,
+            ["#1", "#2", "#3", "#4", "B0002"].map(Transaction::tx_for_reference)
+        );
+        assert_eq!(statements[0].lines[2].bank_reference, None);
+        assert_eq!(statements[0].lines[3].bank_reference, None);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::client::ClientId;
+use crate::date::Date;
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// The status of a client in the registry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientStatus {
+    #[default]
+    Active,
+
+    /// The client's account is locked until they are reinstated.
+    Suspended,
+
+    /// The client's account is locked for good.
+    Closed,
+}
+
+impl ClientStatus {
+    /// Returns whether accounts of clients with this status are locked.
+    pub fn is_locked(&self) -> bool {
+        *self != ClientStatus::Active
+    }
+}
+
+/// A single client of the registry, along with the metadata that is
+/// reported in their statements.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ClientRecord {
+    pub client: ClientId,
+    pub name: Option<String>,
+
+    /// The tier of the client's account, which determines its withdrawal
+    /// limits.
+    pub tier: Option<String>,
+    pub status: Option<ClientStatus>,
+
+    /// The date the client's account was opened.
+    pub opened: Option<Date>,
+
+    /// The approved credit line of the client's account.
+    pub credit_limit: Option<f64>,
+}
+
+impl ClientRecord {
+    pub fn status(&self) -> ClientStatus {
+        self.status.unwrap_or_default()
+    }
+}
+
+/// A ClientRegistry holds the clients known to the Ledger ahead of their
+/// first transaction.
+#[derive(Clone, Debug, Default)]
+pub struct ClientRegistry {
+    clients: HashMap<ClientId, ClientRecord>,
+}
+
+impl ClientRegistry {
+    /// Attempts to read a client registry from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut registry = Self::default();
+        for record in iter.deserialize() {
+            registry.insert(record?);
+        }
+
+        Ok(registry)
+    }
+
+    /// Attempts to read a client registry from the provided reader of a JSON
+    /// array of clients.
+    pub fn from_json_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let records: Vec<serde_json::Value> = serde_json::from_reader(rdr)?;
+
+        let mut registry = Self::default();
+        for mut record in records {
+            // Client ids are read as strings, so numeric ids are converted
+            // to the string they were written as.
+            if let Some(client) = record.get_mut("client").filter(|id| id.is_number()) {
+                *client = serde_json::Value::String(client.to_string());
+            }
+            registry.insert(serde_json::from_value(record)?);
+        }
+
+        Ok(registry)
+    }
+
+    fn insert(&mut self, record: ClientRecord) {
+        self.clients.insert(record.client.clone(), record);
+    }
+
+    /// Returns the registered client with the provided id, if any.
+    pub fn get(&self, client: &ClientId) -> Option<&ClientRecord> {
+        self.clients.get(client)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &ClientRecord> {
+        self.clients.values()
+    }
+}
+
+impl TryFrom<PathBuf> for ClientRegistry {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a client registry from the file located at the
+    /// provided PathBuf. Files with a `.json` extension are read as JSON and
+    /// any other file as CSV.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        let file = std::fs::File::open(&path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_reader(io::BufReader::new(file)),
+            _ => Self::from_reader(file),
+        }
+    }
+}
+
+/// A single row of a bank account mapping.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct BankAccount {
+    account: String,
+    client: ClientId,
+}
+
+/// BankAccounts maps the references of accounts held at our banks, e.g. the
+/// IBANs of client virtual accounts, to the clients they belong to, so that
+/// bank statement entries can be imported as client transactions.
+#[derive(Clone, Debug, Default)]
+pub struct BankAccounts {
+    accounts: HashMap<String, ClientId>,
+}
+
+impl BankAccounts {
+    /// Attempts to read a bank account mapping from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut accounts = Self::default();
+        for account in iter.deserialize() {
+            let BankAccount { account, client } = account?;
+            accounts.accounts.insert(Self::normalize(&account), client);
+        }
+
+        Ok(accounts)
+    }
+
+    /// Account references are compared without whitespace and case, since
+    /// IBANs are often written in groups.
+    fn normalize(account: &str) -> String {
+        account
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase()
+    }
+
+    /// Returns the client the bank account with the provided reference
+    /// belongs to, if any.
+    pub fn client(&self, account: &str) -> Option<&ClientId> {
+        self.accounts.get(&Self::normalize(account))
+    }
+}
+
+impl TryFrom<PathBuf> for BankAccounts {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a bank account mapping from the CSV file located at
+    /// the provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_csv_and_json_registries() {
+        let csv = ClientRegistry::from_reader(
+            "client,name,tier,status,opened,credit_limit\n\
+             1,Acme Ltd,gold,active,2024-01-15,500\n\
+             2,,,closed,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let json = ClientRegistry::from_json_reader(
+            r#"[
+                {"client": 1, "name": "Acme Ltd", "tier": "gold", "status": "active",
+                 "opened": "2024-01-15", "credit_limit": 500},
+                {"client": 2, "status": "closed"}
+            ]"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        for registry in [csv, json] {
+            let acme = registry.get(&ClientId::Numeric(1)).unwrap();
+            assert_eq!(acme.name.as_deref(), Some("Acme Ltd"));
+            assert_eq!(acme.opened, Some(Date::new(2024, 1, 15).unwrap()));
+            assert_eq!(acme.credit_limit, Some(500.0));
+            assert!(!acme.status().is_locked());
+            assert!(registry
+                .get(&ClientId::Numeric(2))
+                .unwrap()
+                .status()
+                .is_locked());
+            assert!(registry.get(&ClientId::Numeric(3)).is_none());
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::client::ClientId;
+use crate::engine::{Transaction, TransactionType};
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Debug;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The action taken on a transaction by a risk rule. Actions are ordered by
+/// severity, and the most severe action of all rules is taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskAction {
+    /// The transaction is applied without an alert.
+    #[default]
+    Allow,
+
+    /// The transaction is applied and an alert is raised.
+    Flag,
+
+    /// The transaction is not applied but held for review, and an alert is
+    /// raised. Held transactions may be released later.
+    Hold,
+
+    /// The transaction is not applied and an alert is raised.
+    Reject,
+}
+
+/// The recent activity of a client that risk rules are evaluated against.
+#[derive(Clone, Debug, Default)]
+pub struct ClientActivity {
+    /// The row and amount of the client's recent deposits, oldest first.
+    pub deposits: VecDeque<(u64, f64)>,
+
+    /// The number of disputes the client has raised.
+    pub disputes: usize,
+
+    /// The number of chargebacks against the client.
+    pub chargebacks: usize,
+}
+
+/// A RiskRule is evaluated before each transaction is applied to the Ledger
+/// and decides whether the transaction should be allowed, flagged, held or
+/// rejected.
+pub trait RiskRule: Debug {
+    /// The name the rule is reported as in the alerts output.
+    fn name(&self) -> &'static str;
+
+    /// Evaluates the transaction, processed at the provided row, against the
+    /// client's recent activity.
+    fn evaluate(
+        &self,
+        transaction: &Transaction,
+        activity: &ClientActivity,
+        row: u64,
+    ) -> RiskAction;
+
+    /// The number of rows of deposit history the rule needs.
+    fn window_rows(&self) -> u64 {
+        0
+    }
+}
+
+/// Flags withdrawals following shortly after a large deposit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LargeDepositWithdrawal {
+    pub min_deposit: f64,
+    pub window_rows: u64,
+    pub action: RiskAction,
+}
+
+impl RiskRule for LargeDepositWithdrawal {
+    fn name(&self) -> &'static str {
+        "large_deposit_withdrawal"
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &Transaction,
+        activity: &ClientActivity,
+        row: u64,
+    ) -> RiskAction {
+        let recent_large_deposit = activity.deposits.iter().any(|(deposit_row, amount)| {
+            deposit_row + self.window_rows >= row && *amount >= self.min_deposit
+        });
+
+        match transaction.transaction_type() {
+            TransactionType::Withdrawal if recent_large_deposit => self.action,
+            _ => RiskAction::Allow,
+        }
+    }
+
+    fn window_rows(&self) -> u64 {
+        self.window_rows
+    }
+}
+
+/// Flags disputes from clients that have already raised a number of
+/// disputes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepeatedDisputes {
+    pub max_disputes: usize,
+    pub action: RiskAction,
+}
+
+impl RiskRule for RepeatedDisputes {
+    fn name(&self) -> &'static str {
+        "repeated_disputes"
+    }
+
+    fn evaluate(&self, transaction: &Transaction, activity: &ClientActivity, _: u64) -> RiskAction {
+        match transaction.transaction_type() {
+            TransactionType::Dispute if activity.disputes >= self.max_disputes => self.action,
+            _ => RiskAction::Allow,
+        }
+    }
+}
+
+/// Flags deposits from clients that have had a chargeback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepositAfterChargeback {
+    pub action: RiskAction,
+}
+
+impl RiskRule for DepositAfterChargeback {
+    fn name(&self) -> &'static str {
+        "deposit_after_chargeback"
+    }
+
+    fn evaluate(&self, transaction: &Transaction, activity: &ClientActivity, _: u64) -> RiskAction {
+        match transaction.transaction_type() {
+            TransactionType::Deposit if activity.chargebacks > 0 => self.action,
+            _ => RiskAction::Allow,
+        }
+    }
+}
+
+/// Flags repeated deposits of round amounts, a common sign of structuring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Structuring {
+    /// Deposits which are a multiple of this amount are considered round.
+    pub multiple: f64,
+
+    /// The number of round deposits, including the evaluated one, within
+    /// the window that trips the rule.
+    pub min_count: usize,
+    pub window_rows: u64,
+    pub action: RiskAction,
+}
+
+impl Structuring {
+    fn is_round(&self, amount: f64) -> bool {
+        amount > 0.0 && self.multiple > 0.0 && amount % self.multiple == 0.0
+    }
+}
+
+impl RiskRule for Structuring {
+    fn name(&self) -> &'static str {
+        "structuring"
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &Transaction,
+        activity: &ClientActivity,
+        row: u64,
+    ) -> RiskAction {
+        match (transaction.transaction_type(), transaction.amount()) {
+            (TransactionType::Deposit, Some(amount)) if self.is_round(amount) => {}
+            _ => return RiskAction::Allow,
+        };
+
+        let count = activity
+            .deposits
+            .iter()
+            .filter(|(deposit_row, amount)| {
+                deposit_row + self.window_rows >= row && self.is_round(*amount)
+            })
+            .count();
+
+        if count + 1 >= self.min_count {
+            self.action
+        } else {
+            RiskAction::Allow
+        }
+    }
+
+    fn window_rows(&self) -> u64 {
+        self.window_rows
+    }
+}
+
+/// A single row of a declarative risk rule configuration. The parameters a
+/// rule uses depend on the rule.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct RuleConfig {
+    rule: String,
+    action: RiskAction,
+    amount: Option<f64>,
+    count: Option<usize>,
+    window_rows: Option<u64>,
+}
+
+impl RuleConfig {
+    fn build(self) -> Result<Box<dyn RiskRule>, String> {
+        let missing = |param: &str| format!("risk rule {} requires a {}", self.rule, param);
+
+        Ok(match self.rule.as_str() {
+            "large_deposit_withdrawal" => Box::new(LargeDepositWithdrawal {
+                min_deposit: self.amount.ok_or_else(|| missing("amount"))?,
+                window_rows: self.window_rows.unwrap_or(1),
+                action: self.action,
+            }),
+            "repeated_disputes" => Box::new(RepeatedDisputes {
+                max_disputes: self.count.ok_or_else(|| missing("count"))?,
+                action: self.action,
+            }),
+            "deposit_after_chargeback" => Box::new(DepositAfterChargeback {
+                action: self.action,
+            }),
+            "structuring" => Box::new(Structuring {
+                multiple: self.amount.ok_or_else(|| missing("amount"))?,
+                min_count: self.count.ok_or_else(|| missing("count"))?,
+                window_rows: self.window_rows.ok_or_else(|| missing("window_rows"))?,
+                action: self.action,
+            }),
+            rule => return Err(format!("unknown risk rule: {}", rule)),
+        })
+    }
+}
+
+/// A single row of the alerts output, describing a transaction that a risk
+/// rule did not allow.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub row: u64,
+    pub tx: u32,
+    pub client: ClientId,
+    pub r#type: TransactionType,
+    pub rule: &'static str,
+    pub action: RiskAction,
+}
+
+/// RiskRules evaluates a set of RiskRules against each transaction and
+/// tracks the client activity they need.
+#[derive(Clone, Debug, Default)]
+pub struct RiskRules {
+    /// The rules are shared rather than copied between clones of the rules.
+    rules: Vec<Arc<dyn RiskRule>>,
+    activity: HashMap<ClientId, ClientActivity>,
+    alerts: Vec<Alert>,
+}
+
+impl RiskRules {
+    /// Adds a rule to the set of rules evaluated against each transaction.
+    pub fn with_rule(mut self, rule: Box<dyn RiskRule>) -> Self {
+        self.rules.push(Arc::from(rule));
+        self
+    }
+
+    /// Attempts to read a declarative risk rule configuration from the
+    /// provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut rules = Self::default();
+        for config in iter.deserialize() {
+            let config: RuleConfig = config?;
+            rules = rules.with_rule(config.build()?);
+        }
+
+        Ok(rules)
+    }
+
+    /// Evaluates every rule against the transaction processed at the provided
+    /// row, raising an alert for each rule that doesn't allow it, and returns
+    /// the most severe action.
+    pub fn evaluate(&mut self, transaction: &Transaction, row: u64) -> RiskAction {
+        let default = ClientActivity::default();
+        let activity = self.activity.get(transaction.client()).unwrap_or(&default);
+
+        let mut action = RiskAction::Allow;
+        for rule in self.rules.iter() {
+            let verdict = rule.evaluate(transaction, activity, row);
+            if verdict != RiskAction::Allow {
+                self.alerts.push(Alert {
+                    row,
+                    tx: transaction.tx(),
+                    client: transaction.client().clone(),
+                    r#type: transaction.transaction_type().clone(),
+                    rule: rule.name(),
+                    action: verdict,
+                });
+            }
+            action = action.max(verdict);
+        }
+
+        action
+    }
+
+    /// Records a transaction that was applied at the provided row in the
+    /// client's activity.
+    pub fn record(&mut self, transaction: &Transaction, row: u64) {
+        if self.rules.is_empty() {
+            return;
+        }
+
+        let window = self.rules.iter().map(|rule| rule.window_rows()).max();
+        let activity = self
+            .activity
+            .entry(transaction.client().clone())
+            .or_default();
+        match transaction.transaction_type() {
+            TransactionType::Deposit => {
+                activity
+                    .deposits
+                    .push_back((row, transaction.amount().unwrap_or_default()));
+            }
+            TransactionType::Dispute => activity.disputes += 1,
+            TransactionType::Chargeback => activity.chargebacks += 1,
+            _ => {}
+        }
+
+        // Forget deposits no rule needs anymore.
+        let window = window.unwrap_or_default();
+        while activity
+            .deposits
+            .front()
+            .is_some_and(|(deposit_row, _)| deposit_row + window < row)
+        {
+            activity.deposits.pop_front();
+        }
+    }
+
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    /// Returns the recorded activity of the provided client, if any.
+    pub fn activity(&self, client: &ClientId) -> Option<&ClientActivity> {
+        self.activity.get(client)
+    }
+
+    /// Restores the rules to an earlier state, dropping the alerts raised
+    /// since and replacing the activity of each provided client, or removing
+    /// it if the client had none.
+    pub fn restore(&mut self, alerts: usize, activity: HashMap<ClientId, Option<ClientActivity>>) {
+        self.alerts.truncate(alerts);
+        for (client, saved) in activity {
+            match saved {
+                Some(saved) => self.activity.insert(client, saved),
+                None => self.activity.remove(&client),
+            };
+        }
+    }
+}
+
+impl TryFrom<PathBuf> for RiskRules {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a declarative risk rule configuration from the CSV
+    /// file located at the provided PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_rules_from_config() {
+        let rules = RiskRules::from_reader(
+            "rule,action,amount,count,window_rows\n\
+             large_deposit_withdrawal,flag,10000,,5\n\
+             repeated_disputes,hold,,3,\n\
+             deposit_after_chargeback,reject,,,\n\
+             structuring,flag,1000,3,20\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let names: Vec<_> = rules.rules.iter().map(|rule| rule.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "large_deposit_withdrawal",
+                "repeated_disputes",
+                "deposit_after_chargeback",
+                "structuring"
+            ]
+        );
+
+        // Rules missing their parameters, or unknown rules, are rejected.
+        assert!(RiskRules::from_reader("rule,action\nstructuring,flag\n".as_bytes()).is_err());
+        assert!(RiskRules::from_reader("rule,action\nvelocity,flag\n".as_bytes()).is_err());
+    }
+}
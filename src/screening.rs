@@ -0,0 +1,132 @@
+use crate::client::ClientId;
+use crate::currency::Currency;
+use crate::engine::TransactionType;
+use csv::Trim;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// A single entry of a blocklist, identifying a client by id and/or name.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BlocklistEntry {
+    client: Option<ClientId>,
+    name: Option<String>,
+
+    /// Why the client is listed, e.g. the sanctions list it appears on.
+    reason: Option<String>,
+}
+
+impl BlocklistEntry {
+    /// Returns the reason a screening hit against this entry is reported
+    /// with.
+    pub fn describe(&self) -> String {
+        let subject = match (&self.client, &self.name) {
+            (Some(client), _) => format!("client {}", client),
+            (None, Some(name)) => format!("name {}", name),
+            (None, None) => String::from("unidentified entry"),
+        };
+
+        match &self.reason {
+            Some(reason) => format!("{} is blocklisted: {}", subject, reason),
+            None => format!("{} is blocklisted", subject),
+        }
+    }
+}
+
+/// A Blocklist holds the clients that accounts may not be created for or
+/// credited to.
+#[derive(Clone, Debug, Default)]
+pub struct Blocklist {
+    clients: HashMap<ClientId, BlocklistEntry>,
+    names: HashMap<String, BlocklistEntry>,
+}
+
+impl Blocklist {
+    /// Attempts to read a blocklist from the provided CSV reader.
+    pub fn from_reader<R: io::Read>(rdr: R) -> Result<Self, Box<dyn Error>> {
+        let mut iter = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(rdr);
+
+        let mut blocklist = Self::default();
+        for entry in iter.deserialize() {
+            let entry: BlocklistEntry = entry?;
+            if let Some(client) = &entry.client {
+                blocklist.clients.insert(client.clone(), entry.clone());
+            }
+            if let Some(name) = &entry.name {
+                blocklist.names.insert(name.to_lowercase(), entry.clone());
+            }
+        }
+
+        Ok(blocklist)
+    }
+
+    /// Returns the entry the provided client id is listed under, if any.
+    pub fn screen_client(&self, client: &ClientId) -> Option<&BlocklistEntry> {
+        self.clients.get(client)
+    }
+
+    /// Returns the entry the provided client name is listed under, if any.
+    /// Names are matched case-insensitively.
+    pub fn screen_name(&self, name: &str) -> Option<&BlocklistEntry> {
+        self.names.get(&name.to_lowercase())
+    }
+}
+
+impl TryFrom<PathBuf> for Blocklist {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to read a blocklist from the CSV file located at the provided
+    /// PathBuf.
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+}
+
+/// A single row of the quarantine output, describing a transaction that was
+/// not applied because of a screening hit.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QuarantineRecord {
+    /// The row of the Ledger's input the transaction was processed at.
+    pub row: u64,
+    pub r#type: TransactionType,
+    pub client: ClientId,
+    pub tx: u32,
+    pub amount: Option<f64>,
+    pub currency: Currency,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_screen_clients_by_id_and_name() {
+        let blocklist = Blocklist::from_reader(
+            "client,name,reason\n\
+             7,,sanctions list\n\
+             ,Jane Doe,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            blocklist
+                .screen_client(&ClientId::Numeric(7))
+                .unwrap()
+                .describe(),
+            "client 7 is blocklisted: sanctions list"
+        );
+        assert!(blocklist.screen_client(&ClientId::Numeric(8)).is_none());
+        assert_eq!(
+            blocklist.screen_name("jane doe").unwrap().describe(),
+            "name Jane Doe is blocklisted"
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::dialect::Dialect;
+use crate::engine::TransactionType;
+use crate::errors::StatementError;
+use csv::StringRecord;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// The columns every transaction file must have.
+const REQUIRED_COLUMNS: [&str; 3] = ["type", "client", "tx"];
+
+/// The columns a transaction file may have.
+const KNOWN_COLUMNS: [&str; 10] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "currency",
+    "to_currency",
+    "date",
+    "source",
+    "batch",
+    "memo",
+];
+
+/// How serious a problem found while validating a transaction file is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The row would stop processing, or be processed incorrectly.
+    Error,
+
+    /// The row would be processed, but is likely a mistake.
+    Warning,
+}
+
+/// A single row of the validation report, describing a structural problem
+/// with a transaction file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Problem {
+    /// The line of the file the problem was found at. The header is line 1.
+    pub line: u64,
+    pub column: Option<String>,
+    pub severity: Severity,
+    pub problem: String,
+}
+
+/// A Validation holds every structural problem found in a transaction file,
+/// read without applying any of its rows to a Ledger.
+#[derive(Clone, Debug, Default)]
+pub struct Validation {
+    problems: Vec<Problem>,
+}
+
+impl Validation {
+    /// Attempts to validate the transactions of the provided CSV reader,
+    /// which are read and mapped to Transaction columns by the provided
+    /// dialect. Only errors reading the input are returned, every problem with
+    /// its content is collected instead.
+    pub fn from_reader<R: io::Read>(rdr: R, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        let mut iter = dialect.reader_builder().from_reader(rdr);
+
+        let mut validation = Self::default();
+        let headers = dialect.headers(&mut iter)?;
+        validation.check_headers(&headers);
+
+        let mut seen: HashMap<(String, String), u64> = HashMap::new();
+        for record in iter.records() {
+            let record = record?;
+            let line = record.position().map(|pos| pos.line()).unwrap_or_default();
+            let field = |column: &str| {
+                headers
+                    .iter()
+                    .position(|header| header == column)
+                    .and_then(|index| record.get(index))
+                    .unwrap_or_default()
+            };
+
+            if record.len() != headers.len() {
+                validation.push(
+                    line,
+                    None,
+                    Severity::Warning,
+                    format!(
+                        "row has {} fields but the header has {}",
+                        record.len(),
+                        headers.len()
+                    ),
+                );
+            }
+
+            let name = dialect.translate_type(field("type"));
+            let r#type = validation.check_type(line, name);
+            if field("client").is_empty() {
+                validation.push(line, Some("client"), Severity::Error, "missing client id");
+            }
+            let tx = field("tx");
+            if tx.parse::<u32>().is_err() {
+                validation.push(
+                    line,
+                    Some("tx"),
+                    Severity::Error,
+                    format!("tx id {:?} is not a number", tx),
+                );
+            }
+            // Amounts are checked against the precision of the row's
+            // currency, or the default currency if it has none.
+            let currency = field("currency").parse().unwrap_or_default();
+            validation.check_amount(line, r#type.as_ref(), name, field("amount"), currency);
+            for column in ["currency", "to_currency"] {
+                let value = field(column);
+                if let Err(err) = Self::parse_optional::<Currency>(value) {
+                    validation.push(line, Some(column), Severity::Error, err);
+                }
+            }
+            if let Err(err) = Self::parse_optional::<Date>(field("date")) {
+                validation.push(line, Some("date"), Severity::Error, err);
+            }
+            if r#type == Some(TransactionType::Exchange) && field("to_currency").is_empty() {
+                validation.push(
+                    line,
+                    Some("to_currency"),
+                    Severity::Error,
+                    "exchange is missing the currency to exchange to",
+                );
+            }
+
+            // The tx id of any row that creates a transaction must be unique
+            // within its source, while disputes, resolves, chargebacks and
+            // refunds reuse the tx id of the transaction they reference.
+            let creates = !matches!(
+                r#type,
+                None | Some(
+                    TransactionType::Dispute
+                        | TransactionType::Resolve
+                        | TransactionType::Chargeback
+                        | TransactionType::Refund
+                )
+            );
+            if creates && !tx.is_empty() {
+                let key = (field("source").to_string(), tx.to_string());
+                if let Some(first) = seen.get(&key) {
+                    validation.push(
+                        line,
+                        Some("tx"),
+                        Severity::Error,
+                        format!("duplicate tx id {} first seen on line {}", tx, first),
+                    );
+                } else {
+                    seen.insert(key, line);
+                }
+            }
+        }
+
+        Ok(validation)
+    }
+
+    fn push(
+        &mut self,
+        line: u64,
+        column: Option<&str>,
+        severity: Severity,
+        problem: impl Into<String>,
+    ) {
+        self.problems.push(Problem {
+            line,
+            column: column.map(String::from),
+            severity,
+            problem: problem.into(),
+        });
+    }
+
+    fn check_headers(&mut self, headers: &StringRecord) {
+        for column in REQUIRED_COLUMNS {
+            if !headers.iter().any(|header| header == column) {
+                self.push(
+                    1,
+                    Some(column),
+                    Severity::Error,
+                    format!("missing required column {}", column),
+                );
+            }
+        }
+
+        for (index, header) in headers.iter().enumerate() {
+            if headers.iter().take(index).any(|earlier| earlier == header) {
+                self.push(
+                    1,
+                    Some(header),
+                    Severity::Error,
+                    format!("duplicate column {}", header),
+                );
+            } else if !KNOWN_COLUMNS.contains(&header) {
+                self.push(
+                    1,
+                    Some(header),
+                    Severity::Warning,
+                    format!("unknown column {} is ignored", header),
+                );
+            }
+        }
+    }
+
+    /// Returns the type of the row, if it is a known TransactionType.
+    fn check_type(&mut self, line: u64, value: &str) -> Option<TransactionType> {
+        let deserializer = StrDeserializer::<ValueError>::new(value);
+        match TransactionType::deserialize(deserializer) {
+            Ok(r#type) => Some(r#type),
+            Err(_) => {
+                self.push(
+                    line,
+                    Some("type"),
+                    Severity::Error,
+                    format!("unknown transaction type {:?}", value),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_amount(
+        &mut self,
+        line: u64,
+        r#type: Option<&TransactionType>,
+        name: &str,
+        value: &str,
+        currency: Currency,
+    ) {
+        let column = Some("amount");
+        if value.is_empty() {
+            if let Some(
+                TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::CreditLimit
+                | TransactionType::Accrue
+                | TransactionType::Exchange,
+            ) = r#type
+            {
+                let problem = format!("{} is missing an amount", name);
+                self.push(line, column, Severity::Error, problem);
+            }
+            return;
+        }
+
+        let amount = match value.parse::<f64>() {
+            Ok(amount) if amount.is_finite() => amount,
+            _ => {
+                let problem = format!("amount {:?} is not a number", value);
+                self.push(line, column, Severity::Error, problem);
+                return;
+            }
+        };
+        if amount < 0.0 {
+            let problem = format!("amount {} is negative", value);
+            self.push(line, column, Severity::Error, problem);
+        }
+        let decimals = value
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len());
+        let precision = currency.precision().max(0) as usize;
+        if decimals > precision {
+            let problem = format!(
+                "amount {} has more than {} decimal places for {} and is rounded",
+                value, precision, currency
+            );
+            self.push(line, column, Severity::Warning, problem);
+        }
+
+        // Disputes, resolves and chargebacks usually apply to the whole of the
+        // transaction they reference.
+        if let Some(
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback,
+        ) = r#type
+        {
+            let problem = format!(
+                "{} has an amount, so only applies to part of the transaction",
+                name
+            );
+            self.push(line, column, Severity::Warning, problem);
+        }
+    }
+
+    fn parse_optional<T: std::str::FromStr<Err = String>>(value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        value.parse::<T>().map(|_| ())
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns whether the file has no problems of error severity.
+    pub fn is_valid(&self) -> bool {
+        self.problems
+            .iter()
+            .all(|problem| problem.severity != Severity::Error)
+    }
+
+    /// Attempts to validate the transactions of the CSV file located at the
+    /// provided PathBuf, read with the provided dialect.
+    pub fn from_path(path: PathBuf, dialect: &Dialect) -> Result<Self, Box<dyn Error>> {
+        Self::from_reader(std::fs::File::open(path)?, dialect)
+    }
+
+    /// Attempts to generate a CSV report of every problem found.
+    pub fn generate_report(&self) -> Result<String, StatementError> {
+        let mut buf = Vec::new();
+        {
+            let mut wtr = csv::Writer::from_writer(&mut buf);
+            for problem in self.problems.iter() {
+                wtr.serialize(problem)?;
+            }
+            let _ = wtr.flush();
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_report_every_problem() {
+        let validation = Validation::from_reader(
+            "type,client,tx,amount,note\n\
+             deposit,1,1,10.0,\n\
+             deposit,1,1,5.0,\n\
+             withdrawal,1,2,,\n\
+             dispute,1,1,2.0,\n\
+             transfer,1,x,-1.00001,\n\
+             deposit,,3,1,\n"
+                .as_bytes(),
+            &Dialect::default(),
+        )
+        .unwrap();
+
+        assert!(!validation.is_valid());
+        assert_eq!(
+            validation.generate_report().unwrap(),
+            "line,column,severity,problem\n\
+             1,note,warning,unknown column note is ignored\n\
+             3,tx,error,duplicate tx id 1 first seen on line 2\n\
+             4,amount,error,withdrawal is missing an amount\n\
+             5,amount,warning,\"dispute has an amount, so only applies to part of the transaction\"\n\
+             6,type,error,\"unknown transaction type \"\"transfer\"\"\"\n\
+             6,tx,error,\"tx id \"\"x\"\" is not a number\"\n\
+             6,amount,error,amount -1.00001 is negative\n\
+             6,amount,warning,amount -1.00001 has more than 4 decimal places for USD and is rounded\n\
+             7,client,error,missing client id\n"
+        );
+
+        // Amounts are checked against the precision of the row's currency.
+        let validation = Validation::from_reader(
+            "type,client,tx,amount,currency\n\
+             deposit,1,1,1.5,JPY\n\
+             deposit,1,2,1.2345,BHD\n\
+             deposit,1,3,1.235,BHD\n\
+             deposit,1,4,1.2345,\n"
+                .as_bytes(),
+            &Dialect::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            validation.generate_report().unwrap(),
+            "line,column,severity,problem\n\
+             2,amount,warning,amount 1.5 has more than 0 decimal places for JPY and is rounded\n\
+             3,amount,warning,amount 1.2345 has more than 3 decimal places for BHD and is rounded\n"
+        );
+
+        // Files are read with the partner's dialect, so its delimiter, column
+        // names and transaction types are valid.
+        let dialect = Dialect::from_reader(
+            "setting,name,value\n\
+             delimiter,,;\n\
+             column,customer,client\n\
+             type,credit,deposit\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let validation = Validation::from_reader(
+            "type;customer;tx;amount\n\
+             credit;1;1;10.0\n\
+             credit;1;2;\n"
+                .as_bytes(),
+            &dialect,
+        )
+        .unwrap();
+        assert_eq!(
+            validation.generate_report().unwrap(),
+            "line,column,severity,problem\n\
+             3,amount,error,deposit is missing an amount\n"
+        );
+
+        // Files missing a header are reported as missing the required columns.
+        let validation =
+            Validation::from_reader("deposit,1,1,1.0\n".as_bytes(), &Dialect::default()).unwrap();
+        assert_eq!(
+            validation
+                .problems()
+                .iter()
+                .filter(|problem| problem.problem.starts_with("missing required column"))
+                .count(),
+            3
+        );
+    }
+}